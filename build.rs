@@ -2,14 +2,18 @@ use clap_complete::{generate_to, Shell};
 use clap_mangen::Man;
 use std::io::Error;
 use std::path::Path;
-use std::{fs::File, path::PathBuf};
+use std::{collections::BTreeMap, fs::File, path::PathBuf};
 
 use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 include!("src/dirs_config.rs");
 include!("src/install_cmd.rs");
 include!("src/uninstall.rs");
+include!("src/list.rs");
+include!("src/rpm_files.rs");
+include!("src/completions.rs");
+include!("src/print_dirs.rs");
 include!("src/opts.rs");
 
 fn build_shell_completion(outdir: &Path) -> Result<(), Error> {
@@ -17,7 +21,7 @@ fn build_shell_completion(outdir: &Path) -> Result<(), Error> {
     let shells = Shell::value_variants();
 
     for shell in shells {
-        generate_to(*shell, &mut opts, "rinstall", &outdir)?;
+        generate_to(*shell, &mut opts, "rinstall", outdir)?;
     }
 
     Ok(())
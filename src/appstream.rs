@@ -0,0 +1,40 @@
+use std::fs;
+
+use camino::Utf8Path;
+use color_eyre::{eyre::Context, Result};
+use quick_xml::{events::Event, Reader};
+
+// Get the component id of an AppStream metadata file, i.e. the content of
+// the <id> element of the root <component>
+pub fn component_id(path: &Utf8Path) -> Result<Option<String>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("unable to read file {:?}", path))?;
+    let mut reader = Reader::from_str(&contents);
+    reader.trim_text(true);
+    let mut depth = 0;
+    let mut in_id = false;
+    loop {
+        match reader
+            .read_event()
+            .with_context(|| format!("unable to parse {:?}", path))?
+        {
+            Event::Start(element) => {
+                depth += 1;
+                in_id = depth == 2 && element.local_name().as_ref() == b"id";
+            }
+            Event::End(_) => {
+                depth -= 1;
+                in_id = false;
+            }
+            Event::Text(text) if in_id => {
+                return Ok(Some(
+                    text.unescape()
+                        .with_context(|| format!("unable to parse {:?}", path))?
+                        .into_owned(),
+                ));
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
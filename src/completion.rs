@@ -0,0 +1,70 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use camino::Utf8PathBuf;
+use serde::Deserialize;
+use void::Void;
+
+use crate::{install_entry::InstallEntry, templating::TemplatingMode};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Completion {
+    #[serde(rename(deserialize = "src"))]
+    pub source: Utf8PathBuf,
+    #[serde(rename(deserialize = "dst"))]
+    pub destination: Option<Utf8PathBuf>,
+    #[serde(default, rename(deserialize = "tmpl"))]
+    pub templating: Option<TemplatingMode>,
+    #[serde(default)]
+    pub rename: BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+    // Run the source, e.g. the built executable, with these arguments and
+    // install its output instead of the source itself
+    pub args: Option<Vec<String>>,
+    // The command completed by the file, used to name it as expected by the
+    // shell, e.g. _<command> for zsh
+    pub command: Option<String>,
+    // Only install the entry when this feature is enabled with --features
+    #[serde(default)]
+    pub when: Option<String>,
+    // Template variables of this entry only, overriding the directories
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Completion {
+    const fn new_with_source(source: Utf8PathBuf) -> Self {
+        Self {
+            source,
+            destination: None,
+            templating: None,
+            rename: BTreeMap::new(),
+            args: None,
+            command: None,
+            when: None,
+            vars: BTreeMap::new(),
+        }
+    }
+}
+
+impl From<Completion> for InstallEntry {
+    fn from(completion: Completion) -> Self {
+        Self {
+            source: completion.source,
+            destination: completion.destination,
+            templating: completion.templating,
+            rename: completion.rename,
+            when: completion.when,
+            vars: completion.vars,
+        }
+    }
+}
+
+impl FromStr for Completion {
+    // This implementation of `from_str` can never fail, so use the impossible
+    // `Void` type as the error type.
+    type Err = Void;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new_with_source(Utf8PathBuf::from(s)))
+    }
+}
@@ -0,0 +1,5 @@
+#[derive(Parser, Clone)]
+pub struct GenerateCompletions {
+    #[clap(value_enum, help = "The shell to generate the completion script for")]
+    pub shell: Shell,
+}
@@ -0,0 +1,22 @@
+use std::io;
+
+use clap::{CommandFactory, Parser};
+use clap_complete::{generate, Shell};
+use color_eyre::Result;
+
+use crate::Opts;
+
+include!("completions.rs");
+
+impl GenerateCompletions {
+    pub fn run(&self) -> Result<()> {
+        generate(
+            self.shell,
+            &mut Opts::command(),
+            "rinstall",
+            &mut io::stdout(),
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use std::io::{self, Read, Write};
+
+use camino::Utf8Path;
+use color_eyre::{
+    eyre::{bail, Context},
+    Result,
+};
+use flate2::{read::GzDecoder, write::GzEncoder};
+use xz2::{read::XzDecoder, write::XzEncoder};
+
+use crate::install_cmd_impl::ManCompression;
+
+// The extensions of the files that are already compressed
+static COMPRESSED_EXTENSIONS: &[&str] = &["gz", "zst", "xz", "bz2"];
+
+impl ManCompression {
+    pub const fn extension(&self) -> Option<&'static str> {
+        match self {
+            ManCompression::Gzip => Some("gz"),
+            ManCompression::Zstd => Some("zst"),
+            ManCompression::Xz => Some("xz"),
+            ManCompression::None => None,
+        }
+    }
+
+    pub fn compress(
+        &self,
+        contents: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let compressed: io::Result<Vec<u8>> = match self {
+            ManCompression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+                encoder.write_all(&contents)?;
+                encoder.finish()
+            }
+            ManCompression::Zstd => zstd::encode_all(contents.as_slice(), 19),
+            ManCompression::Xz => {
+                let mut encoder = XzEncoder::new(Vec::new(), 9);
+                encoder.write_all(&contents)?;
+                encoder.finish()
+            }
+            ManCompression::None => Ok(contents),
+        };
+
+        compressed.context("unable to compress file")
+    }
+}
+
+// Decompress a file using the algorithm matching its extension
+pub fn decompress_file(
+    path: &Utf8Path,
+    contents: Vec<u8>,
+) -> Result<Vec<u8>> {
+    fn read_all(mut decoder: impl Read) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        decoder.read_to_end(&mut decompressed)?;
+        Ok(decompressed)
+    }
+
+    let decompressed = match split_compressed_extension(path.as_str()).1 {
+        Some("gz") => read_all(GzDecoder::new(contents.as_slice())),
+        Some("zst") => zstd::decode_all(contents.as_slice()),
+        Some("xz") => read_all(XzDecoder::new(contents.as_slice())),
+        Some(extension) => bail!(
+            "unable to decompress {:?}, {} is not supported",
+            path,
+            extension
+        ),
+        None => Ok(contents),
+    };
+
+    decompressed.with_context(|| format!("unable to decompress {:?}", path))
+}
+
+// Split a file name into its name without the compression extension and
+// the compression extension, e.g. foo.1.gz into foo.1 and gz
+pub fn split_compressed_extension(name: &str) -> (&str, Option<&str>) {
+    match name.rsplit_once('.') {
+        Some((base, extension)) if COMPRESSED_EXTENSIONS.contains(&extension) => {
+            (base, Some(extension))
+        }
+        _ => (name, None),
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+
+use camino::Utf8PathBuf;
+use color_eyre::{
+    eyre::{ensure, Context},
+    Result,
+};
+use serde::Deserialize;
+
+use crate::{
+    install_entry::InstallEntry,
+    templating::{Templating, TemplatingMode},
+    Dirs,
+};
+
+// An entry installed into a directory chosen by the package, for the
+// locations that are not covered by the other entry types, e.g. /opt/<pkg>
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomEntry {
+    // The installation directory, the @dir@ placeholders are replaced
+    pub directory: String,
+    #[serde(rename(deserialize = "src"))]
+    pub source: Utf8PathBuf,
+    #[serde(rename(deserialize = "dst"))]
+    pub destination: Option<Utf8PathBuf>,
+    #[serde(default, rename(deserialize = "tmpl"))]
+    pub templating: Option<TemplatingMode>,
+    #[serde(default)]
+    pub rename: BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+    // Only install the entry when this feature is enabled with --features
+    #[serde(default)]
+    pub when: Option<String>,
+    // Template variables of this entry only, overriding the directories
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+}
+
+impl CustomEntry {
+    // The directory with its placeholders replaced, e.g. @prefix@/lib/foo
+    pub fn install_dir(
+        &self,
+        dirs: &Dirs,
+    ) -> Result<Utf8PathBuf> {
+        let mut templating = Templating {
+            contents: self.directory.clone(),
+        };
+        templating
+            .apply(dirs, TemplatingMode::Replace, &BTreeMap::new())
+            .with_context(|| {
+                format!("unable to replace the placeholders of {:?}", self.directory)
+            })?;
+        let install_dir = Utf8PathBuf::from(templating.contents);
+        ensure!(
+            !install_dir.as_str().contains('@'),
+            "the directory {:?} contains an unknown placeholder",
+            self.directory
+        );
+        ensure!(
+            install_dir.is_absolute(),
+            "the directory {:?} must be absolute, found {:?}",
+            self.directory,
+            install_dir
+        );
+
+        Ok(install_dir)
+    }
+}
+
+impl From<CustomEntry> for InstallEntry {
+    fn from(entry: CustomEntry) -> Self {
+        Self {
+            source: entry.source,
+            destination: entry.destination,
+            templating: entry.templating,
+            rename: entry.rename,
+            when: entry.when,
+            vars: entry.vars,
+        }
+    }
+}
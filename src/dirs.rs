@@ -1,4 +1,4 @@
-use std::env;
+use std::{collections::BTreeMap, env};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::{eyre::ensure, Result};
@@ -22,6 +22,13 @@ pub struct Dirs {
     pub mandir: Option<Utf8PathBuf>,
     pub pam_modulesdir: Option<Utf8PathBuf>,
     pub systemd_unitsdir: Utf8PathBuf,
+    pub bash_completionsdir: Option<Utf8PathBuf>,
+    pub elvish_completionsdir: Option<Utf8PathBuf>,
+    pub fish_completionsdir: Option<Utf8PathBuf>,
+    pub zsh_completionsdir: Option<Utf8PathBuf>,
+    pub kde_servicesdir: Utf8PathBuf,
+    pub qt_pluginsdir: Option<Utf8PathBuf>,
+    pub section_dirs: BTreeMap<String, Utf8PathBuf>,
 }
 
 impl Dirs {
@@ -46,6 +53,17 @@ impl Dirs {
             mandir: dirs_config.mandir.map(Utf8PathBuf::from),
             pam_modulesdir: dirs_config.pam_modulesdir.map(Utf8PathBuf::from),
             systemd_unitsdir: Utf8PathBuf::from(dirs_config.systemd_unitsdir.unwrap()),
+            bash_completionsdir: dirs_config.bash_completionsdir.map(Utf8PathBuf::from),
+            elvish_completionsdir: dirs_config.elvish_completionsdir.map(Utf8PathBuf::from),
+            fish_completionsdir: dirs_config.fish_completionsdir.map(Utf8PathBuf::from),
+            zsh_completionsdir: dirs_config.zsh_completionsdir.map(Utf8PathBuf::from),
+            kde_servicesdir: Utf8PathBuf::from(dirs_config.kde_servicesdir.unwrap()),
+            qt_pluginsdir: dirs_config.qt_pluginsdir.map(Utf8PathBuf::from),
+            section_dirs: dirs_config
+                .section_dirs
+                .into_iter()
+                .map(|(section, dir)| (section, Utf8PathBuf::from(dir)))
+                .collect(),
         };
 
         if system {
@@ -78,17 +96,56 @@ impl Dirs {
             sysconfdir,
             localstatedir,
             runstatedir,
-            systemd_unitsdir
+            systemd_unitsdir,
+            kde_servicesdir
         );
+
+        for dir in [
+            &mut self.prefix,
+            &mut self.exec_prefix,
+            &mut self.sbindir,
+            &mut self.includedir,
+            &mut self.docdir,
+            &mut self.mandir,
+            &mut self.pam_modulesdir,
+            &mut self.bash_completionsdir,
+            &mut self.elvish_completionsdir,
+            &mut self.fish_completionsdir,
+            &mut self.zsh_completionsdir,
+            &mut self.qt_pluginsdir,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.section_dirs.values_mut())
+        {
+            if dir.is_relative() {
+                *dir = Utf8Path::new(home).join(&dir);
+            }
+        }
     }
 
-    /// Check that all paths are absolute
+    // The directory where the entries of a section are installed, unless it has
+    // been remapped in section_dirs
+    pub fn section_dir(
+        &self,
+        section: &str,
+        install_dir: &Utf8Path,
+    ) -> Utf8PathBuf {
+        self.section_dirs
+            .get(section)
+            .map_or_else(|| install_dir.to_path_buf(), Utf8PathBuf::clone)
+    }
+
+    /// Check that all paths are absolute, a relative path (e.g. a mistyped
+    /// --prefix) would install the files relative to the current directory
     fn check_absolute_paths(&self) -> Result<()> {
         macro_rules! check_abs_path_impl {
             ($var:expr, $name:tt) => {
                 ensure!(
                     $var.is_absolute(),
-                    "{}, with path '{}', is not an absolute path",
+                    "{}, with path '{}', is not an absolute path; system-wide installations \
+                     require absolute paths, check the value given on the command line or \
+                     in the configuration (e.g. --prefix /usr/local)",
                     $name,
                     $var
                 );
@@ -111,6 +168,8 @@ impl Dirs {
             };
         }
 
+        // The other directories are usually relative to the prefix, report it first
+        check_abs_path_opt!(prefix, "prefix", exec_prefix, "exec_prefix");
         check_abs_path!(
             bindir,
             "bindir",
@@ -129,14 +188,14 @@ impl Dirs {
             runstatedir,
             "runstatedir",
             systemd_unitsdir,
-            "systemd-unitsdir"
+            "systemd-unitsdir",
+            kde_servicesdir,
+            "kde_servicesdir"
         );
 
         check_abs_path_opt!(
-            prefix,
-            "prefix",
-            exec_prefix,
-            "exec_prefix",
+            sbindir,
+            "sbindir",
             includedir,
             "includedir",
             docdir,
@@ -144,9 +203,24 @@ impl Dirs {
             mandir,
             "mandir",
             pam_modulesdir,
-            "pam_modulesdir"
+            "pam_modulesdir",
+            bash_completionsdir,
+            "bash_completionsdir",
+            elvish_completionsdir,
+            "elvish_completionsdir",
+            fish_completionsdir,
+            "fish_completionsdir",
+            zsh_completionsdir,
+            "zsh_completionsdir",
+            qt_pluginsdir,
+            "qt_pluginsdir"
         );
 
+        for (section, dir) in &self.section_dirs {
+            let name = format!("section_dirs.{}", section);
+            check_abs_path_impl!(dir, name);
+        }
+
         Ok(())
     }
 }
@@ -1,21 +1,19 @@
-#[derive(Args, Deserialize, Clone)]
+#[derive(Args, Serialize, Deserialize, Clone, Default)]
 pub struct DirsConfig {
     #[clap(
         long,
-        requires = "system",
         env,
         help = concat!("A prefix used in constructing the default values of the directories",
-                       " listed below. (system only)",
-                       " [default: /usr/local]")
+                       " listed below.",
+                       " [system default: /usr/local] [user default: $HOME/.local]")
     )]
     pub prefix: Option<String>,
     #[clap(
         long,
-        requires = "system",
         env,
         help = concat!("A prefix used in constructing the default values of some of the",
-                       " variables listed below. (system only)",
-                       " [default: @prefix@])]")
+                       " variables listed below.",
+                       " [default: @prefix@]")
     )]
     pub exec_prefix: Option<String>,
     #[clap(
@@ -42,6 +40,25 @@ pub struct DirsConfig {
                        " [system default: @exec_prefix@/lib] [user default: $HOME/.local/lib]")
     )]
     pub libdir: Option<String>,
+    #[clap(
+        long,
+        requires = "system",
+        env,
+        help = concat!("Append the multiarch tuple of the system, e.g. x86_64-linux-gnu, to the",
+                       " default libdir, as done by Debian and its derivatives. The tuple is",
+                       " detected with dpkg-architecture or gcc. (system only)")
+    )]
+    #[serde(default)]
+    pub multiarch: bool,
+    #[clap(
+        long,
+        requires = "system",
+        env,
+        help = concat!("The name of the libraries directory used in the default libdir, e.g.",
+                       " lib64 on multilib distributions. (system only)",
+                       " [default: lib]")
+    )]
+    pub libdir_name: Option<String>,
     #[clap(
         long,
         requires = "system",
@@ -94,11 +111,11 @@ pub struct DirsConfig {
     pub runstatedir: Option<String>,
     #[clap(
         long,
-        requires = "system",
         env,
         help = concat!("The directory for installing header files to be included by user programs",
-                       " with the C ‘#include’ preprocessor directive. (system only)",
-                       " [default: @prefix@/include]")
+                       " with the C ‘#include’ preprocessor directive.",
+                       " [system default: @prefix@/include]",
+                       " [user default: .local/include]")
     )]
     pub includedir: Option<String>,
     #[clap(
@@ -135,4 +152,57 @@ pub struct DirsConfig {
                        "[default: @libdir@/systemd]")
     )]
     pub systemd_unitsdir: Option<String>,
+    #[clap(
+        long,
+        env,
+        help = concat!("The directory for installing the bash completions.",
+                       " [system default: @datarootdir@/bash-completion/completions]",
+                       " [user default: @datarootdir@/bash-completion]")
+    )]
+    pub bash_completionsdir: Option<String>,
+    #[clap(
+        long,
+        env,
+        help = concat!("The directory for installing the elvish completions.",
+                       " [default: @datarootdir@/elvish/lib]")
+    )]
+    pub elvish_completionsdir: Option<String>,
+    #[clap(
+        long,
+        requires = "system",
+        env,
+        help = concat!("The directory for installing the fish completions. (system only)",
+                       " [default: @datarootdir@/fish/vendor_completions.d]")
+    )]
+    pub fish_completionsdir: Option<String>,
+    #[clap(
+        long,
+        requires = "system",
+        env,
+        help = concat!("The directory for installing the zsh completions. (system only)",
+                       " [default: @datarootdir@/zsh/site-functions]")
+    )]
+    pub zsh_completionsdir: Option<String>,
+    #[clap(
+        long,
+        env,
+        help = concat!("The directory for installing the KDE service files, e.g.",
+                       " @datarootdir@/kservices6 for KDE Frameworks 6.",
+                       " [default: @datarootdir@/kservices5]")
+    )]
+    pub kde_servicesdir: Option<String>,
+    #[clap(
+        long,
+        requires = "system",
+        env,
+        help = concat!("The directory for installing the Qt plugins, e.g. @libdir@/qt6/plugins",
+                       " for Qt 6. (system only)",
+                       " [default: @libdir@/qt5/plugins]")
+    )]
+    pub qt_pluginsdir: Option<String>,
+    // Install the entries of some sections into a different directory, e.g.
+    // systemd-units: /nix/store/...-foo/lib/systemd/system
+    #[clap(skip)]
+    #[serde(default)]
+    pub section_dirs: BTreeMap<String, String>,
 }
@@ -1,12 +1,12 @@
-use std::fs;
+use std::{collections::BTreeMap, fs, process::Command};
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use clap::Args;
 use color_eyre::{
-    eyre::{ensure, ContextCompat, WrapErr},
+    eyre::{bail, ensure, ContextCompat, WrapErr},
     Result,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use xdg::BaseDirectories;
 
 lazy_static! {
@@ -31,6 +31,37 @@ impl DirsConfig {
     pub fn load(
         config: Option<&str>,
         system: bool,
+        package: Option<Self>,
+        opts: &Self,
+    ) -> Result<Self> {
+        let mut dirs_config = Self::merged(config, system, package, opts)?;
+        dirs_config.replace_placeholders(system)?;
+
+        Ok(dirs_config)
+    }
+
+    // Merge the defaults, the configuration files, the package and the command line
+    // arguments, without replacing the placeholders
+    pub fn merged(
+        config: Option<&str>,
+        system: bool,
+        package: Option<Self>,
+        opts: &Self,
+    ) -> Result<Self> {
+        Self::merged_with_system_config(
+            Utf8Path::new("/etc/rinstall.yml"),
+            config,
+            system,
+            package,
+            opts,
+        )
+    }
+
+    fn merged_with_system_config(
+        system_config: &Utf8Path,
+        config: Option<&str>,
+        system: bool,
+        package: Option<Self>,
         opts: &Self,
     ) -> Result<Self> {
         let mut dirs_config = if system {
@@ -39,24 +70,37 @@ impl DirsConfig {
             Self::user_config()
         };
 
-        let config_file = if let Some(config_file) = config {
-            let config_file = Utf8PathBuf::from(config_file);
-            ensure!(config_file.exists(), "config file does not exist");
-            config_file
-        } else if system {
-            Utf8PathBuf::from("/etc/rinstall.yml")
+        // The configuration of the installation type is always read, then the file
+        // passed with --config overrides its values. The system configuration contains
+        // the system directories, so it is not read for user installations
+        let mut config_files = vec![if system {
+            system_config.to_path_buf()
         } else {
             Utf8PathBuf::from_path_buf(XDG.place_config_file("rinstall.yml")?).unwrap()
-        };
-        if config_file.exists() {
+        }];
+        if let Some(config_file) = config {
+            let config_file = Utf8PathBuf::from(config_file);
+            ensure!(
+                config_file.exists(),
+                "config file {:?} does not exist",
+                config_file
+            );
+            config_files.push(config_file);
+        }
+        for config_file in config_files.iter().filter(|file| file.exists()) {
             let config_from_file = serde_yaml::from_str(
-                &fs::read_to_string(&config_file)
+                &fs::read_to_string(config_file)
                     .with_context(|| format!("unable to read file {:?}", config_file))?,
-            )?;
+            )
+            .with_context(|| format!("unable to parse file {:?}", config_file))?;
             dirs_config.merge(system, config_from_file);
         }
+        // The directories set by the package take precedence over the configuration
+        // but not over the command line arguments
+        if let Some(package) = package {
+            dirs_config.merge(system, package);
+        }
         dirs_config.merge(system, opts.clone());
-        dirs_config.replace_placeholders(system)?;
 
         Ok(dirs_config)
     }
@@ -69,6 +113,8 @@ impl DirsConfig {
             bindir: Some("@exec_prefix@/bin".to_string()),
             sbindir: Some("@exec_prefix@/sbin".to_string()),
             libdir: Some("@exec_prefix@/lib".to_string()),
+            multiarch: false,
+            libdir_name: None,
             libexecdir: Some("@exec_prefix@/libexec".to_string()),
             datarootdir: Some("@prefix@/share".to_string()),
             datadir: Some("@prefix@/share".to_string()),
@@ -80,28 +126,44 @@ impl DirsConfig {
             mandir: Some("@datarootdir@/man".to_string()),
             pam_modulesdir: Some("@libdir@/security".to_string()),
             systemd_unitsdir: Some("@libdir@/systemd".to_string()),
+            bash_completionsdir: Some("@datarootdir@/bash-completion/completions".to_string()),
+            elvish_completionsdir: Some("@datarootdir@/elvish/lib".to_string()),
+            fish_completionsdir: Some("@datarootdir@/fish/vendor_completions.d".to_string()),
+            zsh_completionsdir: Some("@datarootdir@/zsh/site-functions".to_string()),
+            kde_servicesdir: Some("@datarootdir@/kservices5".to_string()),
+            qt_pluginsdir: Some("@libdir@/qt5/plugins".to_string()),
+            section_dirs: BTreeMap::new(),
         }
     }
 
     #[must_use]
     pub fn user_config() -> Self {
         Self {
-            prefix: None,
-            exec_prefix: None,
-            bindir: Some(".local/bin".to_string()),
+            prefix: Some(".local".to_string()),
+            exec_prefix: Some("@prefix@".to_string()),
+            bindir: Some("@exec_prefix@/bin".to_string()),
             sbindir: None,
-            libdir: Some(".local/lib".to_string()),
-            libexecdir: Some(".local/libexec".to_string()),
+            libdir: Some("@exec_prefix@/lib".to_string()),
+            multiarch: false,
+            libdir_name: None,
+            libexecdir: Some("@exec_prefix@/libexec".to_string()),
             datarootdir: Some("@XDG_DATA_HOME@".to_string()),
             datadir: Some("@XDG_DATA_HOME@".to_string()),
             sysconfdir: Some("@XDG_CONFIG_HOME@".to_string()),
             localstatedir: Some("@XDG_DATA_HOME@".to_string()),
             runstatedir: Some("@XDG_RUNTIME_DIR@".to_string()),
-            includedir: None,
+            includedir: Some("@prefix@/include".to_string()),
             docdir: None,
             mandir: None,
             pam_modulesdir: None,
             systemd_unitsdir: Some("@sysconfdir@/systemd".to_string()),
+            bash_completionsdir: Some("@datarootdir@/bash-completion".to_string()),
+            elvish_completionsdir: Some("@datarootdir@/elvish/lib".to_string()),
+            fish_completionsdir: None,
+            zsh_completionsdir: None,
+            kde_servicesdir: Some("@datarootdir@/kservices5".to_string()),
+            qt_pluginsdir: None,
+            section_dirs: BTreeMap::new(),
         }
     }
 
@@ -129,6 +191,7 @@ impl DirsConfig {
             bindir,
             sbindir,
             libdir,
+            libdir_name,
             libexecdir,
             datarootdir,
             datadir,
@@ -139,8 +202,16 @@ impl DirsConfig {
             docdir,
             mandir,
             pam_modulesdir,
-            systemd_unitsdir
+            systemd_unitsdir,
+            bash_completionsdir,
+            elvish_completionsdir,
+            fish_completionsdir,
+            zsh_completionsdir,
+            kde_servicesdir,
+            qt_pluginsdir
         );
+        self.multiarch |= config.multiarch;
+        self.section_dirs.extend(config.section_dirs);
     }
 
     fn merge_user_conf(
@@ -150,6 +221,8 @@ impl DirsConfig {
         update_fields!(
             self,
             config,
+            prefix,
+            exec_prefix,
             bindir,
             libdir,
             libexecdir,
@@ -158,8 +231,13 @@ impl DirsConfig {
             sysconfdir,
             localstatedir,
             runstatedir,
-            systemd_unitsdir
+            includedir,
+            systemd_unitsdir,
+            bash_completionsdir,
+            elvish_completionsdir,
+            kde_servicesdir
         );
+        self.section_dirs.extend(config.section_dirs);
     }
 
     pub fn replace_placeholders(
@@ -167,6 +245,7 @@ impl DirsConfig {
         system: bool,
     ) -> Result<()> {
         if system {
+            self.replace_multiarch_placeholder()?;
             self.replace_root_placeholders();
         } else {
             self.replace_user_placeholders(&XDG)
@@ -176,6 +255,43 @@ impl DirsConfig {
         Ok(())
     }
 
+    // Debian and its derivatives install the libraries into a directory named after
+    // the multiarch tuple, e.g. /usr/lib/x86_64-linux-gnu. The tuple can be used in
+    // libdir with the @multiarch@ placeholder, which is added to the default libdir
+    // by --multiarch. The multilib distributions use lib64 instead, set by --libdir-name
+    fn replace_multiarch_placeholder(&mut self) -> Result<()> {
+        let defaults = Self::system_config();
+        if let Some(libdir_name) = &self.libdir_name {
+            ensure!(
+                !libdir_name.is_empty() && !libdir_name.contains('/'),
+                "libdir_name '{}' is not a valid directory name",
+                libdir_name
+            );
+        }
+        if (self.multiarch || self.libdir_name.is_some()) && self.libdir == defaults.libdir {
+            let libdir_name = self.libdir_name.as_deref().unwrap_or("lib");
+            self.libdir = Some(if self.multiarch {
+                format!("@exec_prefix@/{}/@multiarch@", libdir_name)
+            } else {
+                format!("@exec_prefix@/{}", libdir_name)
+            });
+            // The systemd units are not installed in the multiarch or lib64 directory
+            if self.systemd_unitsdir == defaults.systemd_unitsdir {
+                self.systemd_unitsdir = Some("@prefix@/lib/systemd".to_string());
+            }
+        }
+        if self.libdir.as_ref().unwrap().contains("@multiarch@") {
+            self.libdir = Some(
+                self.libdir
+                    .as_ref()
+                    .unwrap()
+                    .replace("@multiarch@", &multiarch_tuple()?),
+            );
+        }
+
+        Ok(())
+    }
+
     fn replace_root_placeholders(&mut self) {
         macro_rules! replace {
             ( $replacement:ident, $needle:literal, $($var:ident),* ) => {
@@ -205,7 +321,13 @@ impl DirsConfig {
             docdir,
             mandir,
             pam_modulesdir,
-            systemd_unitsdir
+            systemd_unitsdir,
+            bash_completionsdir,
+            elvish_completionsdir,
+            fish_completionsdir,
+            zsh_completionsdir,
+            kde_servicesdir,
+            qt_pluginsdir
         );
 
         replace!(
@@ -217,8 +339,24 @@ impl DirsConfig {
             libexecdir
         );
         replace!(localstatedir, "@localstatedir@", runstatedir);
-        replace!(datarootdir, "@datarootdir@", docdir, mandir);
-        replace!(libdir, "@libdir@", pam_modulesdir, systemd_unitsdir);
+        replace!(
+            datarootdir,
+            "@datarootdir@",
+            docdir,
+            mandir,
+            bash_completionsdir,
+            elvish_completionsdir,
+            fish_completionsdir,
+            zsh_completionsdir,
+            kde_servicesdir
+        );
+        replace!(
+            libdir,
+            "@libdir@",
+            pam_modulesdir,
+            systemd_unitsdir,
+            qt_pluginsdir
+        );
     }
 
     fn replace_user_placeholders(
@@ -236,6 +374,14 @@ impl DirsConfig {
             };
         }
 
+        let prefix = Utf8PathBuf::from(self.prefix.as_ref().unwrap().trim_end_matches('/'));
+        replace!(exec_prefix, "@prefix@", prefix);
+        replace!(includedir, "@prefix@", prefix);
+        let exec_prefix =
+            Utf8PathBuf::from(self.exec_prefix.as_ref().unwrap().trim_end_matches('/'));
+        replace!(bindir, "@exec_prefix@", exec_prefix);
+        replace!(libdir, "@exec_prefix@", exec_prefix);
+        replace!(libexecdir, "@exec_prefix@", exec_prefix);
         replace!(datarootdir, "@XDG_DATA_HOME@", xdg.get_data_home());
         replace!(datadir, "@XDG_DATA_HOME@", xdg.get_data_home());
         replace!(sysconfdir, "@XDG_CONFIG_HOME@", xdg.get_config_home());
@@ -246,7 +392,106 @@ impl DirsConfig {
         replace!(runstatedir, "@XDG_RUNTIME_DIR@", runtime_directory);
         replace!(systemd_unitsdir, "@XDG_CONFIG_HOME@", xdg.get_config_home());
         replace!(systemd_unitsdir, "@sysconfdir@", xdg.get_config_home());
+        let datarootdir =
+            Utf8PathBuf::from(self.datarootdir.as_ref().unwrap().trim_end_matches('/'));
+        replace!(bash_completionsdir, "@datarootdir@", datarootdir);
+        replace!(elvish_completionsdir, "@datarootdir@", datarootdir);
+        replace!(kde_servicesdir, "@datarootdir@", datarootdir);
 
         Ok(())
     }
 }
+
+// Get the multiarch tuple of the system, preferring dpkg-architecture
+// which returns the tuple used by the distribution
+fn multiarch_tuple() -> Result<String> {
+    let commands: [&[&str]; 2] = [
+        &["dpkg-architecture", "-qDEB_HOST_MULTIARCH"],
+        &["gcc", "-dumpmachine"],
+    ];
+    for command in commands {
+        let Ok(output) = Command::new(command[0]).args(&command[1..]).output() else {
+            continue;
+        };
+        let tuple = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !tuple.is_empty() {
+            return Ok(tuple);
+        }
+    }
+    bail!("unable to detect the multiarch tuple, neither dpkg-architecture nor gcc are available");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn system_config(name: &str) -> Utf8PathBuf {
+        let system_config = Utf8PathBuf::from_path_buf(env::temp_dir())
+            .unwrap()
+            .join(format!("rinstall-{}-{}.yml", name, process::id()));
+        fs::write(
+            &system_config,
+            "prefix: /opt/site\nbindir: /opt/site/bin\nsection_dirs:\n  exe: /opt/site/tools\n",
+        )
+        .unwrap();
+        system_config
+    }
+
+    #[test]
+    fn system_config_system_install() {
+        let system_config = system_config("system-config-system");
+        let dirs = DirsConfig::merged_with_system_config(
+            &system_config,
+            None,
+            true,
+            None,
+            &DirsConfig::default(),
+        )
+        .unwrap();
+        fs::remove_file(&system_config).unwrap();
+        assert_eq!(dirs.prefix.as_deref(), Some("/opt/site"));
+        assert_eq!(dirs.bindir.as_deref(), Some("/opt/site/bin"));
+        assert_eq!(dirs.section_dirs.len(), 1);
+    }
+
+    #[test]
+    fn system_config_user_install() {
+        let system_config = system_config("system-config-user");
+        let dirs = DirsConfig::merged_with_system_config(
+            &system_config,
+            None,
+            false,
+            None,
+            &DirsConfig::default(),
+        )
+        .unwrap();
+        fs::remove_file(&system_config).unwrap();
+        assert_ne!(dirs.prefix.as_deref(), Some("/opt/site"));
+        assert_ne!(dirs.bindir.as_deref(), Some("/opt/site/bin"));
+        assert!(!dirs
+            .section_dirs
+            .values()
+            .any(|dir| dir.as_str() == "/opt/site/tools"));
+    }
+
+    #[test]
+    fn shipped_root_config_parses() {
+        let config: DirsConfig =
+            serde_yaml::from_str(include_str!("../config/root/rinstall.yml")).unwrap();
+        assert_eq!(config.bindir.as_deref(), Some("@exec_prefix@/bin"));
+        assert_eq!(
+            config.bash_completionsdir.as_deref(),
+            Some("@datarootdir@/bash-completion/completions")
+        );
+    }
+
+    #[test]
+    fn shipped_user_config_parses() {
+        let config: DirsConfig =
+            serde_yaml::from_str(include_str!("../config/user/rinstall.yml")).unwrap();
+        assert_eq!(config.prefix.as_deref(), Some(".local"));
+        assert_eq!(config.bindir.as_deref(), Some("@exec_prefix@/bin"));
+    }
+}
@@ -1,7 +1,12 @@
 use std::str::FromStr;
 
 use camino::Utf8PathBuf;
-use color_eyre::{eyre::ensure, Result};
+use color_eyre::{
+    eyre::{bail, ensure},
+    Result,
+};
+use colored::Colorize;
+use log::warn;
 use serde::Deserialize;
 use void::Void;
 
@@ -18,6 +23,9 @@ pub struct Icon {
     pub dimensions: Option<String>,
     #[serde(default)]
     pub pixmaps: bool,
+    // Only install the entry when this feature is enabled with --features
+    #[serde(default)]
+    pub when: Option<String>,
 }
 
 impl Icon {
@@ -29,28 +37,38 @@ impl Icon {
             theme: None,
             dimensions: None,
             pixmaps: true,
+            when: None,
         }
     }
 
-    pub fn get_destination(&self) -> Result<Utf8PathBuf> {
-        let dest = if self.pixmaps {
-            Utf8PathBuf::from("pixmaps").join("")
+    // The directory of the icon theme, relative to datarootdir
+    pub fn theme_dir(&self) -> Option<Utf8PathBuf> {
+        if self.pixmaps {
+            None
         } else {
+            let default_theme: &'static str = "hicolor";
+            let theme = self.theme.as_deref().unwrap_or(default_theme);
+            Some(Utf8PathBuf::from("icons").join(theme))
+        }
+    }
+
+    pub fn get_destination(&self) -> Result<Utf8PathBuf> {
+        if let Some(theme) = &self.theme {
             ensure!(
-                self.dimensions.is_some(),
-                "dimensions must be set for all non pixmaps icons"
+                !theme.is_empty() && !theme.contains('/') && theme != "." && theme != "..",
+                "invalid theme name '{}' for icon {:?}",
+                theme,
+                self.source
             );
+        }
 
-            let default_theme: &'static str = "hicolor";
+        let dest = if let Some(theme_dir) = self.theme_dir() {
             let default_icon_type: &'static str = "apps";
-            let theme = self.theme.as_deref().unwrap_or(default_theme);
             let icon_type = self.icon_type.as_deref().unwrap_or(default_icon_type);
 
-            Utf8PathBuf::from("icons")
-                .join(theme)
-                .join(self.dimensions.as_ref().unwrap())
-                .join(icon_type)
-                .join("")
+            theme_dir.join(self.dimensions()?).join(icon_type).join("")
+        } else {
+            Utf8PathBuf::from("pixmaps").join("")
         };
 
         if let Some(destination) = &self.destination {
@@ -59,6 +77,68 @@ impl Icon {
             Ok(dest)
         }
     }
+
+    // Use the dimensions set by the user or infer them from the source path,
+    // either by its extension, its directories (48x48/foo.png) or its
+    // file name (foo-48.png)
+    fn dimensions(&self) -> Result<String> {
+        // svg icons can only go in the scalable (or symbolic) directory
+        if self.source.extension() == Some("svg") {
+            match self.dimensions.as_deref() {
+                Some(dimensions @ ("scalable" | "symbolic")) => return Ok(dimensions.to_string()),
+                Some(dimensions) => warn!(
+                    "svg icon {} cannot have dimensions {}, using {} instead",
+                    self.source.as_str().yellow().bold(),
+                    dimensions.bright_black(),
+                    "scalable".bright_black()
+                ),
+                None => {}
+            }
+            return Ok("scalable".to_string());
+        }
+
+        if let Some(dimensions) = &self.dimensions {
+            return Ok(dimensions.clone());
+        }
+
+        if let Some(dimensions) = self
+            .source
+            .parent()
+            .into_iter()
+            .flat_map(|parent| parent.iter())
+            .find(|dir| is_icon_size(dir))
+        {
+            return Ok(dimensions.to_string());
+        }
+
+        if let Some((_, size)) = self
+            .source
+            .file_stem()
+            .and_then(|stem| stem.rsplit_once(['-', '_']))
+        {
+            if is_icon_size(size) {
+                return Ok(size.to_string());
+            }
+            if !size.is_empty() && size.chars().all(|c| c.is_ascii_digit()) {
+                return Ok(format!("{}x{}", size, size));
+            }
+        }
+
+        bail!(
+            "unable to infer the dimensions of icon {:?}, please set them using 'dimensions'",
+            self.source
+        );
+    }
+}
+
+// Check if s is an icon size in the form 48x48 or 48x48@2
+fn is_icon_size(s: &str) -> bool {
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let (size, scale) = s.split_once('@').unwrap_or((s, "1"));
+    is_number(scale)
+        && size
+            .split_once('x')
+            .is_some_and(|(width, height)| is_number(width) && is_number(height))
 }
 
 impl FromStr for Icon {
@@ -70,3 +150,47 @@ impl FromStr for Icon {
         Ok(Self::new_with_source(Utf8PathBuf::from(s)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn theme_icon(source: &str) -> Icon {
+        Icon {
+            pixmaps: false,
+            ..Icon::new_with_source(Utf8PathBuf::from(source))
+        }
+    }
+
+    #[test]
+    fn svg_icon_is_scalable() {
+        let mut icon = theme_icon("icons/foo.svg");
+        assert_eq!(icon.dimensions().unwrap(), "scalable");
+        assert_eq!(
+            icon.get_destination().unwrap(),
+            Utf8PathBuf::from("icons/hicolor/scalable/apps/")
+        );
+
+        icon.dimensions = Some("48x48".to_string());
+        assert_eq!(icon.dimensions().unwrap(), "scalable");
+        icon.dimensions = Some("symbolic".to_string());
+        assert_eq!(icon.dimensions().unwrap(), "symbolic");
+    }
+
+    #[test]
+    fn png_icon_dimensions() {
+        let icon = theme_icon("icons/48x48/foo.png");
+        assert_eq!(icon.dimensions().unwrap(), "48x48");
+        assert_eq!(
+            icon.get_destination().unwrap(),
+            Utf8PathBuf::from("icons/hicolor/48x48/apps/")
+        );
+
+        assert_eq!(theme_icon("foo-64.png").dimensions().unwrap(), "64x64");
+        assert_eq!(
+            theme_icon("foo_32x32@2.png").dimensions().unwrap(),
+            "32x32@2"
+        );
+        assert!(theme_icon("foo.png").dimensions().is_err());
+    }
+}
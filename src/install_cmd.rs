@@ -2,45 +2,147 @@
 pub struct InstallCmd {
     #[clap(help = "Path to the rinstall.yml configuration", from_global)]
     pub config: Option<String>,
+    #[clap(help = "The format of the messages", from_global)]
+    pub format: OutputFormat,
+    #[clap(help = "Do not print anything on the stdout", from_global)]
+    pub quiet: bool,
     #[clap(
         long = "system",
         help = "Perform a system-wide installation",
         global = true
     )]
     pub system: bool,
+    #[clap(
+        long = "user",
+        help = "Perform a user installation, the default when --system is not given",
+        conflicts_with = "system",
+        global = true
+    )]
+    pub user: bool,
     #[clap(
         short = 'y',
         long = "yes",
         help = "Accept the changes and perform the installation"
     )]
     pub accept_changes: bool,
+    #[clap(
+        long = "dry-run",
+        help = "Only print the changes without performing the installation",
+        conflicts_with = "accept_changes"
+    )]
+    pub dry_run: bool,
+    #[clap(
+        short = 'i',
+        long = "interactive",
+        help = concat!("Perform the installation and ask what to do for each file that",
+                       " already exists"),
+        conflicts_with_all = ["accept_changes", "dry_run", "destdir"]
+    )]
+    pub interactive: bool,
     #[clap(
         short = 'f',
         long = "force",
-        help = "Force the installation by overwriting (non-config) files",
-        conflicts_with = "destdir"
+        help = "Force the installation by overwriting (non-config) files"
     )]
     pub force: bool,
     #[clap(
         long = "update-config",
-        help = "Overwrite the existing configurations of the package",
-        conflicts_with = "destdir"
+        help = "Overwrite the existing configurations of the package"
     )]
     pub update_config: bool,
+    #[clap(
+        long,
+        conflicts_with_all = ["interactive", "root"],
+        help = concat!("Write all the files again, overwriting the existing files and",
+                       " configurations. It implies --update")
+    )]
+    pub reinstall: bool,
+    #[clap(
+        long = "no-clobber",
+        conflicts_with_all = ["force", "update_config", "reinstall", "interactive"],
+        help = concat!("Never overwrite an existing file, skip it instead; only the files",
+                       " with the same contents are kept as unchanged")
+    )]
+    pub no_clobber: bool,
     #[clap(
         long,
         help = concat!("Use the generated binaries and libraries from the",
                        " debug profile (only effective for rust projects)")
     )]
     pub rust_debug_target: bool,
+    #[clap(
+        long,
+        help = concat!("Take all the files from the project directory, as in a",
+                       " release tarball (detected when there is no target/ and no Cargo.toml)")
+    )]
+    pub release_tarball: bool,
+    #[clap(
+        long = "source-dir",
+        help = concat!("Search the sources in this directory before the project directory,",
+                       " e.g. a staging directory; $SOURCEDIR refers to it")
+    )]
+    pub source_dir: Option<std::path::PathBuf>,
+    #[clap(
+        long,
+        help = concat!("Build the project with cargo before installing it",
+                       " (only effective for rust projects)")
+    )]
+    pub build: bool,
+    #[clap(
+        long,
+        help = concat!("Enable all the installed systemd units",
+                       " (skipped when using --destdir)")
+    )]
+    pub enable_units: bool,
+    #[clap(
+        long = "update-mandb",
+        help = concat!("Refresh the whatis database with mandb (or makewhatis) after installing",
+                       " the man pages (skipped when using --destdir)")
+    )]
+    pub update_mandb: bool,
+    #[clap(
+        long = "load-apparmor",
+        help = concat!("Load the installed AppArmor profiles with apparmor_parser",
+                       " (skipped when using --destdir)")
+    )]
+    pub load_apparmor: bool,
+    #[clap(
+        long = "load-selinux",
+        help = concat!("Load the installed SELinux modules with semodule",
+                       " (skipped when using --destdir)")
+    )]
+    pub load_selinux: bool,
+    #[clap(
+        long,
+        help = "Install the libexec files into a subdirectory named after the package"
+    )]
+    pub libexec_subdir: bool,
+    #[clap(
+        long = "kernel-version",
+        help = concat!("The kernel version of the directory where the kernel modules are",
+                       " installed [default: the running kernel, as in uname -r]")
+    )]
+    pub kernel_version: Option<String>,
+    #[clap(
+        long = "polkit-owner",
+        value_name = "USER[:GROUP]",
+        help = "The owner of the installed polkit rules, e.g. polkitd:root"
+    )]
+    pub polkit_owner: Option<String>,
     #[clap(
         short = 'D',
         long,
-        requires = "system",
         help = "Install all the files relative to this directory",
         env
     )]
     pub destdir: Option<String>,
+    #[clap(
+        long,
+        help = concat!("Install into the filesystem image in this directory, checking the",
+                       " existing files in it and skipping the post-install commands"),
+        conflicts_with_all = ["destdir", "update"]
+    )]
+    pub root: Option<String>,
     #[clap(
         long = "skip-pkginfo",
         help = "Skip the installation of rinstall pkginfo, used for uninstallation"
@@ -54,18 +156,179 @@ pub struct InstallCmd {
             .expect("unable to get current directory"),
     )]
     pub package_dir: std::path::PathBuf,
+    #[clap(
+        long,
+        help = concat!("Path to the file describing the packages, used instead of the",
+                       " install.yml in the package directory")
+    )]
+    pub package_file: Option<std::path::PathBuf>,
     #[clap(
         short = 'p',
         long = "pkgs",
         help = "List of packages to install, separated by a comma"
     )]
     pub packages: Vec<String>,
+    #[clap(
+        long = "allow-conflicts",
+        help = "Warn instead of failing when two packages install the same file"
+    )]
+    pub allow_conflicts: bool,
+    #[clap(
+        long,
+        help = concat!("Continue installing the other entries when one of them fails,",
+                       " reporting all the failures at the end")
+    )]
+    pub keep_going: bool,
+    #[clap(
+        long = "show-targets",
+        help = concat!("Print the entries to install with their resolved destination",
+                       " and exit, without installing or checking anything"),
+        conflicts_with_all = ["accept_changes", "dry_run", "interactive", "build"]
+    )]
+    pub show_targets: bool,
+    #[clap(
+        long,
+        requires = "destdir",
+        help = concat!("Compare the files that would be installed with the ones already in",
+                       " --destdir and list the added, changed and removed ones, without",
+                       " installing anything"),
+        conflicts_with_all = ["accept_changes", "interactive", "show_targets"]
+    )]
+    pub diff: bool,
+    #[clap(
+        long,
+        help = concat!("Write the files in place instead of writing a temporary file",
+                       " and renaming it over the destination")
+    )]
+    pub no_atomic: bool,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = concat!("Only install the entries of the given comma-separated types,",
+                       " e.g. exe,man")
+    )]
+    pub only: Vec<String>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Do not install the entries of the given comma-separated types, e.g. data,docs"
+    )]
+    pub skip: Vec<String>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Enable the given comma-separated features, installing the entries that depend on them"
+    )]
+    pub features: Vec<String>,
+    #[clap(
+        long,
+        value_name = "NAME=VALUE",
+        value_parser = parse_define,
+        help = concat!("Define a variable for all the templated files; can be given multiple",
+                       " times and the vars of the entries take precedence over it")
+    )]
+    pub define: Vec<(String, String)>,
+    #[clap(
+        long = "strict-sources",
+        help = "Fail instead of warning when a source points outside of the project directory"
+    )]
+    pub strict_sources: bool,
+    #[clap(
+        long = "ignore-missing-sources",
+        help = concat!("Skip the entries whose source does not exist instead of failing,",
+                       " e.g. the optional artifacts of some build configurations")
+    )]
+    pub ignore_missing_sources: bool,
+    #[clap(
+        long,
+        value_name = "SHA256SUMS",
+        help = concat!("Verify the sources against the SHA-256 checksums in this file,",
+                       " as written by sha256sum, before installing anything")
+    )]
+    pub verify: Option<std::path::PathBuf>,
     #[clap(
         short = 'U',
         long = "update",
         help = "Update the current installed package"
     )]
     pub update: bool,
+    #[clap(
+        long = "prune-stale",
+        requires = "update",
+        help = concat!("Remove the files of the previous installation that are not installed",
+                       " anymore when updating only some entries with --only or --skip")
+    )]
+    pub prune_stale: bool,
+    #[clap(
+        long = "dir-mode",
+        help = "Permissions of the directories created during the installation",
+        default_value = "0755",
+        value_parser = parse_mode
+    )]
+    pub dir_mode: u32,
+    #[clap(
+        long = "file-mode",
+        help = concat!("Permissions of the installed files, the execute bits are",
+                       " added for executable sources"),
+        default_value = "0644",
+        value_parser = parse_mode
+    )]
+    pub file_mode: u32,
+    #[clap(
+        long = "mode-from-umask",
+        conflicts_with_all = ["dir_mode", "file_mode"],
+        help = concat!("Compute the permissions from the umask, 0666 & ~umask for the files",
+                       " and 0777 & ~umask for the directories, like install and cp")
+    )]
+    pub mode_from_umask: bool,
+    #[clap(
+        long = "man-compression",
+        help = "Compress the man pages with the given algorithm, recompressing the ones \
+                that are already compressed; none decompresses them",
+        value_enum
+    )]
+    pub man_compression: Option<ManCompression>,
+    #[clap(
+        long = "no-validate",
+        help = "Do not validate the systemd units with systemd-analyze verify"
+    )]
+    pub no_validate: bool,
+    #[clap(
+        long = "validation-warnings",
+        help = "Whether the warnings printed by the validation are errors",
+        value_enum,
+        default_value = "warn"
+    )]
+    pub validation_warnings: ValidationWarnings,
     #[clap(flatten, next_help_heading = "DIRECTORIES")]
     pub dirs: DirsConfig,
 }
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ValidationWarnings {
+    Warn,
+    Error,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ManCompression {
+    Gzip,
+    Zstd,
+    Xz,
+    None,
+}
+
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| format!("{} is not a valid octal mode", mode))
+}
+
+fn parse_define(define: &str) -> Result<(String, String), String> {
+    define
+        .split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("{} is not a valid definition, expected NAME=VALUE", define))
+}
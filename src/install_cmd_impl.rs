@@ -1,55 +1,520 @@
-use std::fs;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fmt, fs,
+    io::{self, Write},
+    os::unix::fs::{symlink, PermissionsExt},
+    process::{self, Command},
+};
 
-use camino::Utf8Path;
-use clap::Args;
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Args, ValueEnum};
 use color_eyre::{
     eyre::{bail, ensure, Context, ContextCompat},
     Result,
 };
 use colored::Colorize;
-use log::{info, warn};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{error, info, log, warn, Level};
+use semver::Version;
 use walkdir::WalkDir;
 
 use crate::{
+    appstream::component_id,
+    compression::decompress_file,
     dirs::Dirs,
     dirs_config_impl::DirsConfig,
     install_spec::InstallSpec,
     install_target::InstallTarget,
-    package_info::PackageInfo,
+    opts_impl::OutputFormat,
+    package::{TargetOptions, Type},
+    package_info::{checksum, sha256_checksum, InstalledFile, PackageInfo, PackageMetadata},
+    post_install::PostInstall,
     project::Project,
-    templating::Templating,
-    utils::{append_destdir, write_to_file},
+    simple_logger::ProgressBarGuard,
+    systemd_unit::verify_unit,
+    templating::{template_vars, Templating, TemplatingMode},
+    utils::{
+        append_destdir, create_dir_all_with_mode, current_umask, set_mode, set_owner,
+        warn_implicit_user_mode, would, write_to_file, write_to_file_atomic,
+    },
     Uninstall,
 };
 
 include!("install_cmd.rs");
 
-static PROJECTDIR_NEEDLE: &str = "$PROJECTDIR";
+// The targets of a package, computed before installing anything
+pub struct PackageTargets {
+    pub name: String,
+    pub dirs: Dirs,
+    pub project: Project,
+    pub targets: Vec<InstallTarget>,
+    // The rinstall version of the install.yml defining the package
+    pub spec_version: Version,
+    // The version of the package itself, if known
+    pub version: Option<String>,
+}
+
+// The outcome of the installation of all the files, the paths are the ones
+// written, including the destdir or the root
+#[derive(Default)]
+pub struct Report {
+    pub installed: Vec<Utf8PathBuf>,
+    pub unchanged: Vec<Utf8PathBuf>,
+    pub skipped: Vec<(Utf8PathBuf, SkipReason)>,
+    // The sources of the entries that failed with --keep-going
+    pub errors: Vec<(Utf8PathBuf, color_eyre::Report)>,
+}
+
+// Why a file has not been installed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SkipReason {
+    // The config already exists and it has not been installed by rinstall
+    ExistingConfig,
+    // The user chose to keep the existing file with --interactive
+    Declined,
+    // The file already exists and --no-clobber is set
+    NoClobber,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        f.write_str(match self {
+            SkipReason::ExistingConfig => "the config already exists",
+            SkipReason::Declined => "the existing file has been kept",
+            SkipReason::NoClobber => "the file already exists",
+        })
+    }
+}
+
+// The human readable summary printed at the end of the installation
+impl fmt::Display for Report {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "{} installed, {} unchanged, {} skipped",
+            self.installed.len(),
+            self.unchanged.len(),
+            self.skipped.len()
+        )?;
+        if !self.errors.is_empty() {
+            write!(f, ", {} failed", self.errors.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+// Where to write a file whose destination might already exist
+enum ExistingFile {
+    Write(Utf8PathBuf),
+    // The config has been modified, write the new version next to it
+    WriteNew(Utf8PathBuf),
+    Skip(SkipReason),
+}
+
+// The context of the errors happened while installing a target, used
+// by --format json to report the target that failed
+#[derive(Debug)]
+pub struct TargetError {
+    pub target: Utf8PathBuf,
+}
+
+impl fmt::Display for TargetError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        write!(f, "unable to install {}", self.target)
+    }
+}
 
 impl InstallCmd {
     pub fn run(self) -> Result<()> {
-        let dirs_config = DirsConfig::load(self.config.as_deref(), self.system, &self.dirs)?;
-        let dirs = Dirs::new(dirs_config, self.system).context("unable to create dirs")?;
-        let install_spec =
-            InstallSpec::new_from_path(Utf8Path::from_path(&self.package_dir).unwrap())?;
+        if self.show_targets {
+            return self.show_targets();
+        }
+        if self.diff {
+            return self.diff_destdir();
+        }
+        let packages = self.compute_targets()?;
+        let accept_changes = self.applies_changes();
+        let report = self.execute(packages)?;
+
+        if accept_changes {
+            info!("{} {}", "Summary:".bold(), report);
+        } else {
+            info!(
+                "{} {} {}",
+                "DRY RUN".yellow().bold(),
+                "Summary:".bold(),
+                report
+            );
+        }
+        ensure!(
+            report.errors.is_empty(),
+            "{} entries could not be installed, see the errors above",
+            report.errors.len()
+        );
+
+        Ok(())
+    }
+
+    // Whether the files are written: --dry-run always prevents any change to the
+    // filesystem, --interactive installs them asking before replacing the existing ones
+    const fn applies_changes(&self) -> bool {
+        !self.dry_run && (self.accept_changes || self.interactive)
+    }
+
+    // Load install.yml and compute the files to install for the selected packages,
+    // checking for conflicts and validating them, without changing anything
+    pub fn compute_targets(&self) -> Result<Vec<PackageTargets>> {
+        let mut packages = self.package_targets()?;
+        if self.ignore_missing_sources {
+            for package in &mut packages {
+                let project = &package.project;
+                package.targets.retain(|target| {
+                    let exists = project.source_path(&target.source).exists();
+                    if !exists {
+                        warn!(
+                            "the source {} does not exist, skipping it",
+                            target.source.as_str().yellow().bold()
+                        );
+                    }
+                    exists
+                });
+            }
+        }
+        for package in &packages {
+            for target in &package.targets {
+                package
+                    .project
+                    .check_source(&target.source, self.strict_sources)?;
+            }
+        }
+        if let Some(checksums_file) = &self.verify {
+            verify_sources(&packages, Utf8Path::from_path(checksums_file).unwrap())?;
+        }
+        self.check_conflicts(&packages)?;
+        if !self.no_validate {
+            self.validate(&packages)?;
+        }
+
+        Ok(packages)
+    }
+
+    // Compute the targets of the selected packages, without looking at their sources
+    fn package_targets(&self) -> Result<Vec<PackageTargets>> {
+        warn_implicit_user_mode(self.system, self.user);
+        let install_spec = InstallSpec::load(
+            Utf8Path::from_path(&self.package_dir).unwrap(),
+            self.package_file
+                .as_deref()
+                .map(|file| Utf8Path::from_path(file).unwrap()),
+        )?;
 
         // Check if the projectdir is a release tarball instead of the
         // directory containing the source code
-        let is_release_tarball = self.package_dir.join(".tarball").exists();
+        let is_release_tarball = Project::is_release_tarball(
+            Utf8Path::from_path(&self.package_dir).unwrap(),
+            self.release_tarball,
+        );
         let version = install_spec.version.clone();
 
         let packages = install_spec.packages(&self.packages);
+        if self.build
+            && !is_release_tarball
+            && packages
+                .iter()
+                .any(|package| package.project_type() == Type::Rust)
+        {
+            Project::build_rust(
+                Utf8Path::from_path(&self.package_dir).unwrap(),
+                self.rust_debug_target,
+            )?;
+        }
+
+        packages
+            .into_iter()
+            .map(|package| -> Result<PackageTargets> {
+                let dirs_config = DirsConfig::load(
+                    self.config.as_deref(),
+                    self.system,
+                    package.dirs.clone(),
+                    &self.dirs,
+                )?;
+                let dirs = Dirs::new(dirs_config, self.system).context("unable to create dirs")?;
+                let name = package.name.clone().unwrap();
+                let package_version = package.version.clone();
+                let project = Project::new_from_type(
+                    package.project_type(),
+                    Utf8Path::from_path(&self.package_dir).unwrap(),
+                    is_release_tarball,
+                    self.rust_debug_target,
+                    self.source_dir
+                        .as_deref()
+                        .map(|dir| Utf8Path::from_path(dir).unwrap()),
+                )?;
+                let targets = package.targets(
+                    &dirs,
+                    &project,
+                    &version,
+                    &TargetOptions {
+                        system_install: self.system,
+                        man_compression: self.man_compression,
+                        enable_units: self.enable_units,
+                        update_mandb: self.update_mandb,
+                        libexec_subdir: self.libexec_subdir,
+                        only: self.only.clone(),
+                        skip: self.skip.clone(),
+                        features: self.features.clone(),
+                        kernel_version: self.kernel_version.clone(),
+                        polkit_owner: self.polkit_owner.clone(),
+                        load_apparmor: self.load_apparmor,
+                        load_selinux: self.load_selinux,
+                    },
+                )?;
+
+                Ok(PackageTargets {
+                    name,
+                    dirs,
+                    project,
+                    targets,
+                    spec_version: version.clone(),
+                    version: package_version,
+                })
+            })
+            .collect::<Result<Vec<PackageTargets>>>()
+    }
+
+    // Print the targets of --show-targets, as a table or as a JSON array
+    fn show_targets(&self) -> Result<()> {
+        let packages = self.package_targets()?;
+        let targets = packages.iter().flat_map(|package| {
+            package.targets.iter().map(|target| {
+                // The destination of a directory entry is the directory itself
+                let directory = if target.destination.as_str().ends_with('/') {
+                    target.destination.as_path()
+                } else {
+                    target.destination.parent().unwrap_or(&target.destination)
+                };
+                (package.name.as_str(), target, directory)
+            })
+        });
+
+        if self.format == OutputFormat::Json {
+            let targets = targets
+                .map(|(package, target, directory)| {
+                    json::object! {
+                        package: package,
+                        section: target.section,
+                        source: target.source.as_str(),
+                        destination: target.destination.as_str(),
+                        directory: directory.as_str(),
+                        replace: target.replace,
+                        templating: target.templating.as_str(),
+                    }
+                })
+                .collect::<Vec<_>>();
+            println!("{}", json::JsonValue::Array(targets).dump());
+            return Ok(());
+        }
+
+        let mut rows = vec![[
+            "PACKAGE",
+            "SECTION",
+            "SOURCE",
+            "DESTINATION",
+            "DIRECTORY",
+            "REPLACE",
+            "TEMPLATING",
+        ]
+        .map(str::to_string)];
+        rows.extend(targets.map(|(package, target, directory)| {
+            [
+                package.to_string(),
+                target.section.to_string(),
+                target.source.to_string(),
+                target.destination.to_string(),
+                directory.to_string(),
+                target.replace.to_string(),
+                target.templating.as_str().to_string(),
+            ]
+        }));
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.len());
+            }
+        }
+        for row in &rows {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(value, width)| format!("{:width$}", value, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+
+        Ok(())
+    }
+
+    // Compare the files that would be installed with the ones in the destdir, the
+    // removed files are the ones left there by a previous build
+    fn diff_destdir(&self) -> Result<()> {
+        let destdir = Utf8Path::new(self.destdir.as_deref().unwrap());
+        let packages = self.compute_targets()?;
+        let mut installed = HashSet::new();
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for package in &packages {
+            for target in &package.targets {
+                for (source, destination) in target.files(&package.project)? {
+                    let destination = append_destdir(&destination, self.target_root());
+                    let contents =
+                        self.file_contents(target, &source, &package.dirs, &package.project)?;
+                    if !destination.is_file() {
+                        added.push(destination.clone());
+                    } else if fs::read(&destination)
+                        .with_context(|| format!("unable to read file {:?}", destination))?
+                        != contents
+                    {
+                        changed.push(destination.clone());
+                    }
+                    for alias in target.alias_paths(&destination) {
+                        if !alias.is_symlink() {
+                            added.push(alias.clone());
+                        }
+                        installed.insert(alias);
+                    }
+                    installed.insert(destination);
+                }
+            }
+            // The pkginfo always differs, as it contains the installation time
+            if !self.skip_pkg_info {
+                let pkg_info = PackageInfo::new(&package.name, &package.dirs);
+                installed.insert(append_destdir(&pkg_info.path, self.target_root()));
+            }
+        }
+
+        let mut removed = Vec::new();
+        if destdir.exists() {
+            for entry in WalkDir::new(destdir).sort_by_file_name() {
+                let entry = entry?;
+                if entry.file_type().is_dir() {
+                    continue;
+                }
+                let path = Utf8Path::from_path(entry.path())
+                    .with_context(|| format!("{:?} is not a valid UTF-8 path", entry.path()))?;
+                if !installed.contains(path) {
+                    removed.push(path.to_path_buf());
+                }
+            }
+        }
+
+        added.sort();
+        changed.sort();
+        for (paths, status) in [
+            (&added, "added".green()),
+            (&changed, "changed".yellow()),
+            (&removed, "removed".red()),
+        ] {
+            for path in paths {
+                // The paths as installed on the target system
+                println!("{:7}  /{}", status, self.strip_root(path));
+            }
+        }
+        info!(
+            "{} {} added, {} changed, {} removed",
+            "Diff:".bold(),
+            added.len(),
+            changed.len(),
+            removed.len()
+        );
+
+        Ok(())
+    }
+
+    // Install the targets computed by compute_targets, along with the pkginfo of
+    // each package, and run the post install commands
+    pub fn execute(
+        mut self,
+        packages: Vec<PackageTargets>,
+    ) -> Result<Report> {
+        self.accept_changes = self.applies_changes();
+
+        if self.mode_from_umask {
+            let umask = current_umask();
+            self.file_mode = 0o666 & !umask;
+            self.dir_mode = 0o777 & !umask;
+        }
+
+        if self.destdir.is_some() {
+            for (enabled, flag) in [
+                (self.force, "--force"),
+                (self.update_config, "--update-config"),
+                (self.reinstall, "--reinstall"),
+            ] {
+                if enabled {
+                    warn!(
+                        "{} has no effect when using {}, all the files are always written",
+                        flag.bright_black().italic(),
+                        "--destdir".bright_black().italic(),
+                    );
+                }
+            }
+        }
+
+        // indicatif only draws the progress bar when stderr is a terminal
+        let total = packages
+            .iter()
+            .map(|package| package.targets.len())
+            .sum::<usize>();
+        let progress_bar = if self.quiet || self.interactive || self.format == OutputFormat::Json {
+            ProgressBar::hidden()
+        } else {
+            ProgressBar::new(total as u64).with_style(
+                ProgressStyle::with_template("{bar:30} {pos}/{len} {wide_msg}").unwrap(),
+            )
+        };
+        let progress_bar = ProgressBarGuard::new(progress_bar);
+
+        let mut hooks = Vec::new();
+        let mut report = Report::default();
         for package in packages {
-            let mut pkg_info = PackageInfo::new(package.name.as_ref().unwrap(), &dirs);
-            let pkg_info_path = append_destdir(&pkg_info.path, self.destdir.as_deref());
+            let PackageTargets {
+                name,
+                dirs,
+                project,
+                targets,
+                spec_version,
+                version,
+            } = package;
+            let mut pkg_info = PackageInfo::new(&name, &dirs);
+            pkg_info.metadata = Some(PackageMetadata::new(&spec_version, self.system, version));
+            let pkg_info_path = append_destdir(&pkg_info.path, self.target_root());
             let pkg_already_installed = pkg_info_path.exists();
+            // Read the checksums of the previous installation before --update removes it
+            let previous_pkg_info = if pkg_already_installed {
+                Some(PackageInfo::load(&pkg_info_path)?)
+            } else {
+                None
+            };
             info!(
                 "{} {} {}",
                 ">>>".magenta(),
                 "Package".bright_black(),
                 pkg_info.pkg_name.italic().blue()
             );
-            if pkg_already_installed && !self.update {
+            // --reinstall removes the previous installation like --update
+            let update = self.update || self.reinstall;
+            if pkg_already_installed && !update {
                 ensure!(
                     !self.accept_changes,
                     "cannot install {} because it has already been installed",
@@ -62,12 +527,28 @@ impl InstallCmd {
                 )
             }
 
-            if pkg_already_installed && self.update {
+            // Only some entries are installed, so keep the files of the other ones
+            let partial = !self.only.is_empty() || !self.skip.is_empty();
+            let stale_files = match (update, &previous_pkg_info) {
+                (true, Some(previous_pkg_info)) => {
+                    self.stale_files(&targets, &project, previous_pkg_info, partial)?
+                }
+                _ => Vec::new(),
+            };
+            if let (true, true, Some(previous_pkg_info)) = (partial, update, &previous_pkg_info) {
+                pkg_info.files = previous_pkg_info.files.clone();
+                pkg_info.enabled_units = previous_pkg_info.enabled_units.clone();
+                pkg_info.loaded_policies = previous_pkg_info.loaded_policies.clone();
+                pkg_info.directories = previous_pkg_info.directories.clone();
+                self.handle_stale_files(&stale_files, &mut pkg_info)?;
+            } else if pkg_already_installed && update {
                 let uninstall = Uninstall {
                     config: None,
                     accept_changes: self.accept_changes,
-                    force: self.force,
+                    dry_run: self.dry_run,
+                    force: self.force || self.reinstall,
                     system: self.system,
+                    user: !self.system,
                     prefix: None,
                     localstatedir: Some(dirs.localstatedir.as_str().to_owned()),
                     packages: vec![pkg_info.pkg_name.clone()],
@@ -76,24 +557,47 @@ impl InstallCmd {
                 uninstall.run()?;
             }
 
-            let project_type = package.project_type.clone();
-            let project = Project::new_from_type(
-                project_type,
-                Utf8Path::from_path(&self.package_dir).unwrap(),
-                is_release_tarball,
-                self.rust_debug_target,
-            )?;
-
-            let targets = package.targets(&dirs, &version, self.system)?;
-
             for target in targets {
-                self.install_target(
+                progress_bar.set_message(target.source.to_string());
+                // A target whose files cannot be listed fails when installing it
+                for hook in target.post_install_hooks(&project).unwrap_or_default() {
+                    // The units are only enabled and the policies loaded when not using
+                    // destdir or root, record them to undo them when uninstalling
+                    match (&hook, self.target_root()) {
+                        (PostInstall::EnableUnit(unit), None)
+                            if !pkg_info.enabled_units.contains(unit) =>
+                        {
+                            pkg_info.enabled_units.push(unit.clone());
+                        }
+                        (PostInstall::LoadPolicy(policy), None)
+                            if !pkg_info.loaded_policies.contains(policy) =>
+                        {
+                            pkg_info.loaded_policies.push(policy.clone());
+                        }
+                        _ => {}
+                    }
+                    if !hooks.contains(&hook) {
+                        hooks.push(hook);
+                    }
+                }
+                let result = self.install_target(
                     &target,
                     &dirs,
                     &mut pkg_info,
-                    pkg_already_installed,
+                    previous_pkg_info.as_ref(),
                     &project,
-                )?;
+                    &mut report,
+                );
+                match result {
+                    Err(err) if self.keep_going => {
+                        error!("unable to install {}: {:#}", target.source, err);
+                        report.errors.push((target.source.clone(), err));
+                    }
+                    result => result.wrap_err_with(|| TargetError {
+                        target: target.source.clone(),
+                    })?,
+                }
+                progress_bar.inc(1);
             }
 
             if !self.skip_pkg_info {
@@ -103,10 +607,11 @@ impl InstallCmd {
                         "pkginfo".purple().bold(),
                         pkg_info_path.as_str().cyan().bold()
                     );
-                    pkg_info.install(self.destdir.as_deref())?;
+                    pkg_info.install(self.target_root(), self.dir_mode)?;
                 } else {
                     info!(
-                        "Would install {} -> {}",
+                        "{} {} -> {}",
+                        would("install"),
                         "pkginfo".purple().bold(),
                         pkg_info.path.as_str().cyan().bold()
                     );
@@ -114,178 +619,647 @@ impl InstallCmd {
             }
         }
 
+        drop(progress_bar);
+
+        // Skip the commands when using destdir, the files are not in their
+        // final location, or root, the image is offline
+        if self.target_root().is_none() {
+            for hook in hooks {
+                hook.run(self.accept_changes)?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    // The files of the previous installation that are not installed anymore, e.g.
+    // because they have been dropped by the new version of the package. When
+    // updating only some entries, only the files of the selected sections are
+    // checked, the pkginfo written by older rinstall versions does not record them
+    fn stale_files(
+        &self,
+        targets: &[InstallTarget],
+        project: &Project,
+        previous_pkg_info: &PackageInfo,
+        partial: bool,
+    ) -> Result<Vec<InstalledFile>> {
+        let mut installed = HashSet::new();
+        for target in targets {
+            for (_, destination) in self.checked_files(target, project)? {
+                let destination = append_destdir(&destination, self.target_root());
+                for path in target
+                    .alias_paths(&destination)
+                    .iter()
+                    .chain([&destination])
+                {
+                    installed.insert(Utf8Path::new("/").join(self.strip_root(path)));
+                }
+            }
+        }
+        let selected = |section: &str| {
+            (self.only.is_empty() || self.only.iter().any(|only| only == section))
+                && !self.skip.iter().any(|skip| skip == section)
+        };
+
+        let stale_files = previous_pkg_info
+            .files
+            .iter()
+            .filter(|file| !installed.contains(&file.path))
+            .filter(|file| !partial || file.section.as_deref().is_some_and(selected))
+            .cloned()
+            .collect::<Vec<_>>();
+        for file in &stale_files {
+            warn!(
+                "file {} is not installed by {} anymore{}",
+                file.path.as_str().yellow().bold(),
+                previous_pkg_info.pkg_name.blue().italic(),
+                if !partial {
+                    ", it is removed with the previous installation"
+                } else if self.prune_stale {
+                    ""
+                } else {
+                    ", add --prune-stale to remove it"
+                }
+            );
+        }
+
+        Ok(stale_files)
+    }
+
+    // Remove the stale files with --prune-stale, the modified ones are kept
+    // unless --force is given
+    fn handle_stale_files(
+        &self,
+        stale_files: &[InstalledFile],
+        pkg_info: &mut PackageInfo,
+    ) -> Result<()> {
+        if !self.prune_stale {
+            return Ok(());
+        }
+        for stale_file in stale_files {
+            let file = InstalledFile {
+                path: append_destdir(&stale_file.path, self.target_root()),
+                ..stale_file.clone()
+            };
+            if file.exists() {
+                if file.has_been_modified()? && !self.force {
+                    warn!(
+                        "Keeping modified file {}, add {} to remove it",
+                        file.path.as_str().yellow().bold(),
+                        "--force".bright_black().italic(),
+                    );
+                    continue;
+                }
+                if self.accept_changes {
+                    info!("Removing {}", file.path.as_str().cyan().bold());
+                    fs::remove_file(&file.path)
+                        .with_context(|| format!("unable to remove file {:?}", file.path))?;
+                } else {
+                    info!("{} {}", would("remove"), file.path.as_str().cyan().bold());
+                }
+            }
+            pkg_info
+                .files
+                .retain(|installed| installed.path != stale_file.path);
+        }
+
+        Ok(())
+    }
+
+    // Check that two packages do not install different files into the same destination
+    fn check_conflicts(
+        &self,
+        packages: &[PackageTargets],
+    ) -> Result<()> {
+        let mut destinations: HashMap<Utf8PathBuf, (&str, Utf8PathBuf)> = HashMap::new();
+        for package in packages {
+            for target in &package.targets {
+                for (source, destination) in self.checked_files(target, &package.project)? {
+                    match destinations.get(&destination) {
+                        Some((other_pkg, other_source))
+                            if *other_pkg != package.name && *other_source != source =>
+                        {
+                            ensure!(
+                                self.allow_conflicts,
+                                "packages {} and {} both install {:?}",
+                                other_pkg,
+                                package.name,
+                                destination
+                            );
+                            warn!(
+                                "packages {} and {} both install {}, the latter will be used",
+                                other_pkg.blue().italic(),
+                                package.name.blue().italic(),
+                                destination.as_str().yellow().bold()
+                            );
+                        }
+                        _ => {}
+                    }
+                    destinations.insert(destination, (&package.name, source));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // The directory prepended to all the installed paths
+    fn target_root(&self) -> Option<&str> {
+        self.destdir.as_deref().or(self.root.as_deref())
+    }
+
+    // The path of an installed file on the target system
+    fn strip_root<'a>(
+        &self,
+        destination: &'a Utf8Path,
+    ) -> &'a Utf8Path {
+        self.target_root()
+            .map_or(destination, |root| destination.strip_prefix(root).unwrap())
+    }
+
+    // List the files of a target before installing them, with --keep-going the errors
+    // are ignored here and reported when installing the target
+    fn checked_files(
+        &self,
+        target: &InstallTarget,
+        project: &Project,
+    ) -> Result<Vec<(Utf8PathBuf, Utf8PathBuf)>> {
+        match target.files(project) {
+            Err(_) if self.keep_going => Ok(Vec::new()),
+            files => files,
+        }
+    }
+
+    // Check the systemd units and the AppStream metadata before installing them, the
+    // templated units are skipped as their placeholders are replaced only during the
+    // installation
+    fn validate(
+        &self,
+        packages: &[PackageTargets],
+    ) -> Result<()> {
+        let mut systemd_analyze_found = true;
+        for package in packages {
+            for target in &package.targets {
+                match target.section {
+                    "systemd-units" | "systemd-user-units"
+                        if systemd_analyze_found
+                            && target.templating == TemplatingMode::Disabled =>
+                    {
+                        let user = target.section == "systemd-user-units";
+                        for (source, _) in self.checked_files(target, &package.project)? {
+                            if !verify_unit(&source, user, self.validation_warnings)? {
+                                warn!(
+                                    "{} has not been found, skipping the validation of the systemd units",
+                                    "systemd-analyze".yellow().bold()
+                                );
+                                systemd_analyze_found = false;
+                                break;
+                            }
+                        }
+                    }
+                    "appstream-metadata" => {
+                        for (source, destination) in self.checked_files(target, &package.project)? {
+                            check_metainfo_name(&source, &destination)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
         Ok(())
     }
-    pub fn install_target(
+
+    fn install_target(
         &self,
         install_target: &InstallTarget,
         dirs: &Dirs,
         pkg_info: &mut PackageInfo,
-        pkg_already_installed: bool,
+        previous_pkg_info: Option<&PackageInfo>,
         project: &Project,
+        report: &mut Report,
     ) -> Result<()> {
-        let InstallTarget {
-            source,
-            destination,
-            templating,
-            replace,
-        } = &install_target;
-        let destination = append_destdir(destination, self.destdir.as_deref());
-
-        // The source is using the needle to force it to be in the projectdir
-        let source = if let Ok(source) = source.strip_prefix(PROJECTDIR_NEEDLE) {
-            source.to_path_buf()
-        } else if let Some(outputdir) = &project.outputdir {
-            // In this case we are checking if the source exists inside output_dir
-            // If it does we use it
-            let outputdir_source = outputdir.join(source);
-            if outputdir_source.exists() {
-                outputdir_source
-            } else {
-                source.clone()
+        let InstallTarget { replace, .. } = &install_target;
+        let files = install_target.files(project)?;
+        for (source, destination) in &files {
+            let destination = append_destdir(destination, self.target_root());
+            let contents = self.file_contents(install_target, source, dirs, project)?;
+            check_destination_type(&destination)?;
+            if self.is_clobbered(&destination, Some(&contents)) {
+                self.skip_clobbered(source, &destination, report);
+                continue;
             }
-        } else {
-            // Otherwise we use project_dir
-            source.clone()
-        };
-
-        ensure!(source.exists(), "{:?} does not exist", source);
-
-        if source.is_file() {
-            let destination = if destination.as_str().ends_with('/') {
-                destination.join(
+            // A file with the same contents is not owned by the package either, keep
+            // it as it is, including its permissions, and do not record it
+            if self.no_clobber && destination.symlink_metadata().is_ok() {
+                log!(
+                    self.files_log_level(),
+                    "{} {} -> {}",
+                    if self.accept_changes {
+                        "Unchanged".to_string()
+                    } else {
+                        would("keep unchanged")
+                    },
                     source
-                        .file_name()
-                        .with_context(|| format!("unable to get filename for {:?}", source))?,
-                )
+                        .strip_prefix(&self.package_dir)
+                        .unwrap_or(source)
+                        .as_str()
+                        .purple()
+                        .bold(),
+                    destination.as_str().cyan().bold()
+                );
+                report.unchanged.push(destination);
+                continue;
+            }
+            // The destdir is expected to be a fresh directory (e.g. the buildroot of a package),
+            // so write every file, including the configs, without checking for existing ones
+            let destination = if self.destdir.is_none() {
+                match self.handle_existing_files(
+                    source,
+                    &destination,
+                    &contents,
+                    previous_pkg_info,
+                    *replace,
+                )? {
+                    ExistingFile::Write(destination) => destination,
+                    // The config is still owned by the package, keep its recorded checksum
+                    // so that the next updates still know whether it has been modified
+                    ExistingFile::WriteNew(new_destination) => {
+                        if let Some(previous_pkg_info) = previous_pkg_info {
+                            pkg_info.keep_file(previous_pkg_info, self.strip_root(&destination));
+                        }
+                        new_destination
+                    }
+                    ExistingFile::Skip(reason) => {
+                        report.skipped.push((destination, reason));
+                        continue;
+                    }
+                }
             } else {
                 destination
             };
-            // destdir conflicts with force and update-config
-            if self.destdir.is_none()
-                && self.handle_existing_files(
-                    &source,
-                    &destination,
-                    pkg_already_installed,
-                    *replace,
-                )?
-            {
-                return Ok(());
-            }
-            info!(
+            let unchanged = !self.reinstall
+                && destination.is_file()
+                && fs::read(&destination)
+                    .with_context(|| format!("unable to read file {:?}", destination))?
+                    == contents;
+            log!(
+                self.files_log_level(),
                 "{} {} -> {}",
-                if self.accept_changes {
-                    "Installing"
-                } else {
-                    "Would install"
+                match (self.accept_changes, unchanged) {
+                    (true, false) => "Installing".to_string(),
+                    (true, true) => "Unchanged".to_string(),
+                    (false, false) => would("install"),
+                    (false, true) => would("keep unchanged"),
                 },
                 source
-                    .strip_prefix(self.package_dir.as_path())
-                    .unwrap_or(&source)
+                    .strip_prefix(&self.package_dir)
+                    .unwrap_or(source)
                     .as_str()
                     .purple()
                     .bold(),
                 destination.as_str().cyan().bold()
             );
-            if self.accept_changes {
-                fs::create_dir_all(&destination.parent().unwrap()).with_context(|| {
-                    format!("unable to create directory {:?}", destination.parent())
-                })?;
-                if *templating {
-                    let mut templating = Templating::new(&source)?;
-                    templating
-                        .apply(dirs)
-                        .with_context(|| format!("unable to apply templating to {:?}", source))?;
-                    write_to_file(&destination, &templating.contents)?;
-                } else {
-                    fs::copy(&source, &destination).with_context(|| {
-                        format!("unable to copy file {:?} to {:?}", source, destination)
-                    })?;
+            if unchanged {
+                report.unchanged.push(destination.clone());
+            } else {
+                report.installed.push(destination.clone());
+            }
+            if !self.accept_changes {
+                continue;
+            }
+            // The generated files are not executable like their source
+            let mode = if let Some(mode) = install_target.mode {
+                mode
+            } else if install_target.generate_args.is_some() {
+                self.file_mode
+            } else {
+                self.file_mode(source, install_target.is_executable())?
+            };
+            // The files in the destdir are owned by whoever builds the package
+            let owner = match (&install_target.owner, &self.destdir) {
+                (Some(owner), None) => Some(owner.as_str()),
+                _ => None,
+            };
+            // Do not touch the files that are already up to date
+            if !unchanged {
+                for dir in create_dir_all_with_mode(destination.parent().unwrap(), self.dir_mode)? {
+                    pkg_info.add_directory(self.strip_root(&dir));
                 }
-                let dest_wo_destdir = &self
-                    .destdir
-                    .as_ref()
-                    .map_or(destination.as_path(), |destdir| {
-                        destination.strip_prefix(&destdir).unwrap()
-                    });
-                pkg_info.add_file(&destination, dest_wo_destdir, *replace)?;
-            }
-        } else if source.is_dir() {
-            WalkDir::new(&source)
-                .into_iter()
-                .try_for_each(|entry| -> Result<()> {
-                    let entry = entry?;
-                    if !entry.file_type().is_file() {
-                        return Ok(());
-                    }
+            }
+            if unchanged || self.no_atomic {
+                if !unchanged {
+                    write_to_file(&destination, &contents)?;
+                }
+                set_mode(&destination, mode)?;
+                if let Some(owner) = owner {
+                    set_owner(&destination, owner)?;
+                }
+            } else {
+                write_to_file_atomic(&destination, &contents, mode, owner)?;
+            }
+            pkg_info.add_file(
+                &destination,
+                self.strip_root(&destination),
+                *replace,
+                install_target.section,
+            )?;
+        }
+        for (_, destination) in files {
+            let destination = append_destdir(&destination, self.target_root());
+            for alias in install_target.alias_paths(&destination) {
+                self.install_alias(
+                    &alias,
+                    &destination,
+                    install_target.section,
+                    pkg_info,
+                    report,
+                )?;
+            }
+        }
 
-                    let full_path = Utf8Path::from_path(entry.path()).unwrap();
-                    let relative_path = full_path.strip_prefix(&source).with_context(|| {
-                        format!("unable to strip prefix {:?} from {:?}", source, full_path)
-                    })?;
-                    let source = source.join(relative_path);
-                    let destination = destination.join(relative_path);
-                    // destdir conflicts with force and update-config
-                    if self.destdir.is_none()
-                        && self.handle_existing_files(
-                            &source,
-                            &destination,
-                            pkg_already_installed,
-                            *replace,
-                        )?
-                    {
-                        return Ok(());
-                    }
-                    info!(
-                        "{} {} -> {}",
-                        if self.accept_changes {
-                            "Installing"
-                        } else {
-                            "Would install"
-                        },
-                        source
-                            .strip_prefix(&self.package_dir)
-                            .unwrap_or(&source)
-                            .as_str()
-                            .purple()
-                            .bold(),
-                        destination.as_str().cyan().bold()
-                    );
-                    if !self.accept_changes {
-                        return Ok(());
-                    }
-                    fs::create_dir_all(destination.parent().unwrap()).with_context(|| {
-                        format!("unable to create directory {:?}", destination.parent())
-                    })?;
-                    if *templating {
-                        let mut templating = Templating::new(&source)?;
-                        templating.apply(dirs).with_context(|| {
-                            format!("unable to apply templating to {:?}", source)
-                        })?;
-                        write_to_file(&destination, &templating.contents)?;
-                    } else {
-                        fs::copy(&source, &destination).with_context(|| {
-                            format!("unable to copy file {:?} to {:?}", source, destination)
-                        })?;
-                    }
+        Ok(())
+    }
 
-                    Ok(())
-                })?;
+    // The contents written for a file of a target, after generating, templating
+    // and (de)compressing it
+    fn file_contents(
+        &self,
+        install_target: &InstallTarget,
+        source: &Utf8Path,
+        dirs: &Dirs,
+        project: &Project,
+    ) -> Result<Vec<u8>> {
+        let InstallTarget {
+            templating,
+            compression,
+            decompress,
+            ..
+        } = &install_target;
+        let contents = if install_target.generate_args.is_some() {
+            install_target.generate(source, project)?
+        } else if *templating != TemplatingMode::Disabled {
+            let mut template = Templating::new(source)?;
+            template
+                .apply(
+                    dirs,
+                    *templating,
+                    &template_vars(&self.define, &install_target.vars),
+                )
+                .with_context(|| format!("unable to apply templating to {:?}", source))?;
+            template.contents.into_bytes()
+        } else {
+            fs::read(source).with_context(|| format!("unable to read file {:?}", source))?
+        };
+        let contents = if *decompress {
+            decompress_file(source, contents)?
+        } else {
+            contents
+        };
+        compression
+            .compress(contents)
+            .with_context(|| format!("unable to compress {:?}", source))
+    }
+
+    // Create a symlink next to an installed file, pointing to it
+    fn install_alias(
+        &self,
+        alias: &Utf8Path,
+        destination: &Utf8Path,
+        section: &str,
+        pkg_info: &mut PackageInfo,
+        report: &mut Report,
+    ) -> Result<()> {
+        let target = Utf8Path::new(destination.file_name().unwrap());
+        let unchanged = !self.reinstall
+            && alias
+                .read_link_utf8()
+                .is_ok_and(|current_target| current_target == target);
+        if !unchanged && self.is_clobbered(alias, None) {
+            self.skip_clobbered(destination, alias, report);
+            return Ok(());
+        }
+        if !unchanged && alias.symlink_metadata().is_ok() {
+            ensure!(
+                self.force || self.reinstall || !self.accept_changes,
+                "file {:?} already exists, add --force to overwrite it",
+                alias
+            );
+            if !self.accept_changes {
+                warn!(
+                    "file {} already exists, it would be overwritten",
+                    alias.as_str().yellow().bold()
+                );
+            }
+        }
+        log!(
+            self.files_log_level(),
+            "{} {} -> {}",
+            match (self.accept_changes, unchanged) {
+                (true, false) => "Linking".to_string(),
+                (true, true) => "Unchanged".to_string(),
+                (false, false) => would("link"),
+                (false, true) => would("keep unchanged"),
+            },
+            alias.as_str().cyan().bold(),
+            target.as_str().purple().bold()
+        );
+        if unchanged {
+            report.unchanged.push(alias.to_path_buf());
         } else {
-            bail!("{:?} is neither a file nor a directory", source);
+            report.installed.push(alias.to_path_buf());
+        }
+        // --no-clobber does not take over the existing symlinks either
+        if !self.accept_changes || (unchanged && self.no_clobber) {
+            return Ok(());
+        }
+        if !unchanged {
+            if alias.symlink_metadata().is_ok() {
+                fs::remove_file(alias)
+                    .with_context(|| format!("unable to remove file {:?}", alias))?;
+            }
+            symlink(target, alias)
+                .with_context(|| format!("unable to create symlink {:?}", alias))?;
         }
+        pkg_info.add_symlink(self.strip_root(alias), target, section);
 
         Ok(())
     }
 
+    // With --no-clobber, whether writing the destination would overwrite an existing
+    // file; a file with the same contents is not overwritten, only kept as it is
+    fn is_clobbered(
+        &self,
+        destination: &Utf8Path,
+        contents: Option<&[u8]>,
+    ) -> bool {
+        self.no_clobber
+            && destination.symlink_metadata().is_ok()
+            && !contents.is_some_and(|contents| {
+                destination.is_file()
+                    && fs::read(destination).is_ok_and(|current| current == contents)
+            })
+    }
+
+    fn skip_clobbered(
+        &self,
+        source: &Utf8Path,
+        destination: &Utf8Path,
+        report: &mut Report,
+    ) {
+        warn!(
+            "{} {} -> {}, the file already exists",
+            if self.accept_changes {
+                "Skipping".to_string()
+            } else {
+                would("skip")
+            },
+            source
+                .strip_prefix(&self.package_dir)
+                .unwrap_or(source)
+                .as_str()
+                .purple()
+                .bold(),
+            destination.as_str().yellow().bold()
+        );
+        report
+            .skipped
+            .push((destination.to_path_buf(), SkipReason::NoClobber));
+    }
+
+    // The list of files is only printed with --verbose, unless nothing is being changed
+    fn files_log_level(&self) -> Level {
+        if self.accept_changes {
+            Level::Debug
+        } else {
+            Level::Info
+        }
+    }
+
+    // Add the execute bits to --file-mode where it has read bits, if the source
+    // is executable or the file is always an executable. The execute bits of the
+    // source are always kept, and executables can always be run by their owner
+    fn file_mode(
+        &self,
+        source: &Utf8Path,
+        executable: bool,
+    ) -> Result<u32> {
+        let source_mode = fs::metadata(source)
+            .with_context(|| format!("unable to read metadata of {:?}", source))?
+            .permissions()
+            .mode();
+        let mut mode = self.file_mode | (source_mode & 0o111);
+        if executable || source_mode & 0o111 != 0 {
+            mode |= (self.file_mode & 0o444) >> 2;
+        }
+        // With --mode-from-umask the execute bits of the source are masked too,
+        // dir_mode being 0777 & ~umask
+        if self.mode_from_umask {
+            mode &= self.dir_mode;
+        }
+        if executable {
+            mode |= 0o100;
+        }
+
+        Ok(mode)
+    }
+
+    // Ask the user what to do with a file that already exists,
     // return true if the file should be skipped
+    fn prompt_existing_file(
+        &self,
+        destination: &Utf8Path,
+        contents: &[u8],
+    ) -> Result<bool> {
+        loop {
+            eprint!(
+                "{} {} already exists. [o]verwrite, [s]kip, [d]iff, [q]uit? ",
+                "::".blue().bold(),
+                destination.as_str().yellow().bold()
+            );
+            io::stderr().flush()?;
+            let mut answer = String::new();
+            // Stop at the end of the input instead of asking again forever
+            if io::stdin()
+                .read_line(&mut answer)
+                .context("unable to read the answer")?
+                == 0
+            {
+                bail!(
+                    "installation aborted, no answer given for {:?}",
+                    destination
+                );
+            }
+            match answer.trim() {
+                "o" | "O" => return Ok(false),
+                "s" | "S" => return Ok(true),
+                "d" | "D" => show_diff(destination, contents)?,
+                "q" | "Q" => bail!("installation aborted"),
+                _ => {}
+            }
+        }
+    }
+
+    // Return the destination of the file, or None if it should be skipped
     fn handle_existing_files(
         &self,
         source: &Utf8Path,
         destination: &Utf8Path,
-        pkg_already_installed: bool,
+        contents: &[u8],
+        previous_pkg_info: Option<&PackageInfo>,
         replace: bool,
-    ) -> Result<bool> {
+    ) -> Result<ExistingFile> {
+        let pkg_already_installed = previous_pkg_info.is_some();
+        if self.interactive && destination.exists() {
+            // There is nothing to decide when the file would not change
+            if destination.is_file()
+                && fs::read(destination)
+                    .with_context(|| format!("unable to read file {:?}", destination))?
+                    == contents
+            {
+                return Ok(ExistingFile::Write(destination.to_path_buf()));
+            }
+            let skip = self.prompt_existing_file(destination, contents)?;
+            return Ok(if skip {
+                ExistingFile::Skip(SkipReason::Declined)
+            } else {
+                ExistingFile::Write(destination.to_path_buf())
+            });
+        }
+        // --no-clobber has already skipped the existing files
+        if self.no_clobber {
+            return Ok(ExistingFile::Write(destination.to_path_buf()));
+        }
+        // --reinstall overwrites everything, including the modified configs
+        if self.reinstall && destination.exists() {
+            if !replace {
+                warn!(
+                    "config {} {} overwritten by {}",
+                    destination.as_str().yellow().bold(),
+                    if self.accept_changes {
+                        "is being"
+                    } else {
+                        "would be"
+                    },
+                    "--reinstall".bright_black().italic()
+                );
+            }
+            return Ok(ExistingFile::Write(destination.to_path_buf()));
+        }
+        // The checksum recorded by the previous installation tells whether
+        // the file has been modified since then
+        let recorded_checksum = previous_pkg_info
+            .and_then(|pkg_info| pkg_info.file_checksum(self.strip_root(destination)));
+        // The unmodified files of the previous installation are still there when
+        // updating only some of the entries, overwrite them
+        if destination.exists()
+            && replace
+            && recorded_checksum.is_some_and(|recorded_checksum| {
+                checksum(destination).is_ok_and(|checksum| checksum == recorded_checksum)
+            })
+        {
+            return Ok(ExistingFile::Write(destination.to_path_buf()));
+        }
         if destination.exists() && replace {
             if !self.force {
                 if self.accept_changes {
@@ -316,13 +1290,35 @@ impl InstallCmd {
                 } else if !pkg_already_installed {
                     warn!("config {} will be overwritten", destination);
                 }
+            } else if let Some(recorded_checksum) = recorded_checksum {
+                // An unmodified config is updated to the new version, a modified one
+                // is kept and the new version is written next to it if it differs
+                if checksum(destination)? != recorded_checksum
+                    && fs::read(destination)
+                        .with_context(|| format!("unable to read file {:?}", destination))?
+                        != contents
+                {
+                    let new_destination = Utf8PathBuf::from(format!("{}.rnew", destination));
+                    warn!(
+                        "config {} has been modified, {} the new version as {}",
+                        destination.as_str().yellow().bold(),
+                        if self.accept_changes {
+                            "installing"
+                        } else {
+                            "it would install"
+                        },
+                        new_destination.as_str().cyan().bold()
+                    );
+                    return Ok(ExistingFile::WriteNew(new_destination));
+                }
             } else {
-                info!(
+                log!(
+                    self.files_log_level(),
                     "{} config {} -> {}",
                     if self.accept_changes {
-                        "Skipping"
+                        "Skipping".to_string()
                     } else {
-                        "Would skip"
+                        would("skip")
                     },
                     source
                         .strip_prefix(&self.package_dir)
@@ -333,10 +1329,204 @@ impl InstallCmd {
                     destination.as_str().cyan().bold()
                 );
                 // Skip installation
-                return Ok(true);
+                return Ok(ExistingFile::Skip(SkipReason::ExistingConfig));
+            }
+        }
+
+        Ok(ExistingFile::Write(destination.to_path_buf()))
+    }
+}
+
+// Show the differences between an existing file and the contents that would replace it,
+// which are templated or compressed and can differ from the source
+fn show_diff(
+    destination: &Utf8Path,
+    contents: &[u8],
+) -> Result<()> {
+    let new_file = Utf8PathBuf::from_path_buf(env::temp_dir())
+        .ok()
+        .context("the temporary directory is not a valid UTF-8 path")?
+        .join(format!(
+            "rinstall-{}-{}",
+            destination.file_name().unwrap_or_default(),
+            process::id()
+        ));
+    write_to_file(&new_file, contents)?;
+    let status = Command::new("diff")
+        .args(["-u", destination.as_str(), new_file.as_str()])
+        .status();
+    let _ = fs::remove_file(&new_file);
+    match status {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            warn!("{} has not been found", "diff".yellow().bold());
+        }
+        Err(err) => return Err(err).context("unable to run diff"),
+    }
+
+    Ok(())
+}
+
+// Check every source file against the checksums of --verify, failing before anything is
+// installed. The paths in the file are relative to the project directory, like in install.yml
+fn verify_sources(
+    packages: &[PackageTargets],
+    checksums_file: &Utf8Path,
+) -> Result<()> {
+    let contents = fs::read_to_string(checksums_file)
+        .with_context(|| format!("unable to read file {:?}", checksums_file))?;
+    let mut checksums = HashMap::new();
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        // sha256sum marks the files read in binary mode with *
+        let (checksum, path) = line
+            .split_once(char::is_whitespace)
+            .map(|(checksum, path)| (checksum, path.trim_start().trim_start_matches('*')))
+            .with_context(|| format!("invalid line in {:?}: {}", checksums_file, line))?;
+        checksums.insert(
+            Utf8Path::new(path.trim_start_matches("./")).to_path_buf(),
+            checksum.to_lowercase(),
+        );
+    }
+
+    for package in packages {
+        for target in &package.targets {
+            for (source, _) in target.files(&package.project)? {
+                let path = source
+                    .strip_prefix(&package.project.projectdir)
+                    .unwrap_or(&source);
+                let expected = checksums.get(path).with_context(|| {
+                    format!(
+                        "the source {:?} is not listed in {:?}",
+                        path, checksums_file
+                    )
+                })?;
+                let checksum = sha256_checksum(&source)?;
+                ensure!(
+                    &checksum == expected,
+                    "the checksum of {:?} does not match, expected {} but found {}",
+                    path,
+                    expected,
+                    checksum
+                );
             }
         }
+    }
+
+    Ok(())
+}
+
+// Check that a file can be written to the destination, i.e. that neither the
+// destination is a directory nor one of its parents is a file. --force cannot
+// fix these, so fail with a clear error instead of the one of the filesystem
+fn check_destination_type(destination: &Utf8Path) -> Result<()> {
+    ensure!(
+        !destination.is_dir(),
+        "unable to install the file {:?}, a directory exists at its path",
+        destination
+    );
+    if let Some(parent) = destination
+        .ancestors()
+        .skip(1)
+        .find(|parent| parent.symlink_metadata().is_ok())
+    {
+        ensure!(
+            parent.is_dir(),
+            "unable to install the file {:?}, its parent {:?} is a file instead of a directory",
+            destination,
+            parent
+        );
+    }
+
+    Ok(())
+}
+
+// Software centers ignore the AppStream metadata whose file name is not
+// <component-id>.metainfo.xml
+fn check_metainfo_name(
+    source: &Utf8Path,
+    destination: &Utf8Path,
+) -> Result<()> {
+    let Some(id) = component_id(source)? else {
+        warn!(
+            "the AppStream metadata {} does not contain a component id",
+            source.as_str().yellow().bold()
+        );
+        return Ok(());
+    };
+    let expected_name = format!("{}.metainfo.xml", id);
+    if destination.file_name() != Some(&expected_name) {
+        warn!(
+            "the AppStream metadata {} should be installed as {}",
+            destination.as_str().yellow().bold(),
+            expected_name.bright_black()
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::{install_entry::InstallEntry, install_target::FilesPolicy, Opts, SubCommand};
+
+    fn install_cmd(args: &[&str]) -> InstallCmd {
+        let SubCommand::Install(install) =
+            Opts::parse_from(["rinstall", "install"].iter().chain(args)).subcmd
+        else {
+            unreachable!()
+        };
+        *install
+    }
+
+    // A source file with the given permissions
+    fn source_with_mode(
+        name: &str,
+        mode: u32,
+    ) -> Utf8PathBuf {
+        let source = Utf8PathBuf::from_path_buf(env::temp_dir())
+            .unwrap()
+            .join(format!("rinstall-{}-{}", name, process::id()));
+        fs::write(&source, "#!/bin/sh\n").unwrap();
+        set_mode(&source, mode).unwrap();
+        source
+    }
+
+    #[test]
+    fn libexec_is_executable() {
+        let source = source_with_mode("libexec-source", 0o644);
+        let install = install_cmd(&[]);
+        let target = InstallTarget::new(
+            InstallEntry::new_with_source(Utf8PathBuf::from("foo-helper")),
+            Utf8Path::new("/usr/local/libexec"),
+            FilesPolicy::Replace,
+            "libexec",
+        )
+        .unwrap();
+        assert!(target.is_executable());
+        assert_eq!(
+            install.file_mode(&source, target.is_executable()).unwrap(),
+            0o755
+        );
+        // A restrictive --file-mode only gets the execute bit for the owner
+        let install = install_cmd(&["--file-mode", "0600"]);
+        assert_eq!(
+            install.file_mode(&source, target.is_executable()).unwrap(),
+            0o700
+        );
+        fs::remove_file(source).unwrap();
+    }
 
-        Ok(false)
+    #[test]
+    fn executable_source_keeps_execute_bits() {
+        let source = source_with_mode("executable-source", 0o755);
+        let install = install_cmd(&[]);
+        assert_eq!(install.file_mode(&source, false).unwrap(), 0o755);
+        // The execute bits of the source are kept even without the read bits
+        let install = install_cmd(&["--file-mode", "0640"]);
+        assert_eq!(install.file_mode(&source, false).unwrap(), 0o751);
+        fs::remove_file(source).unwrap();
     }
 }
@@ -1,12 +1,14 @@
-use std::{fmt, marker::PhantomData, str::FromStr};
+use std::{collections::BTreeMap, fmt, marker::PhantomData, str::FromStr};
 
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 use serde::{
     de::{self, MapAccess, Visitor},
     Deserialize, Deserializer,
 };
 use void::Void;
 
+use crate::templating::TemplatingMode;
+
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct InstallEntry {
@@ -14,8 +16,19 @@ pub struct InstallEntry {
     pub source: Utf8PathBuf,
     #[serde(rename(deserialize = "dst"))]
     pub destination: Option<Utf8PathBuf>,
+    // None when not set, so that each entry type can use its own default
     #[serde(default, rename(deserialize = "tmpl"))]
-    pub templating: bool,
+    pub templating: Option<TemplatingMode>,
+    // Rename some of the files of a directory, from their path relative to
+    // the source to the new one relative to the destination
+    #[serde(default)]
+    pub rename: BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+    // Only install the entry when this feature is enabled with --features
+    #[serde(default)]
+    pub when: Option<String>,
+    // Template variables of this entry only, overriding the directories
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
 }
 
 impl InstallEntry {
@@ -23,9 +36,21 @@ impl InstallEntry {
         Self {
             source,
             destination: None,
-            templating: false,
+            templating: None,
+            rename: BTreeMap::new(),
+            when: None,
+            vars: BTreeMap::new(),
         }
     }
+
+    // The path that will be installed, i.e. the destination if it is a file,
+    // the source otherwise
+    pub fn installed_path(&self) -> &Utf8Path {
+        self.destination
+            .as_ref()
+            .filter(|destination| !destination.as_str().ends_with('/'))
+            .unwrap_or(&self.source)
+    }
 }
 
 impl FromStr for InstallEntry {
@@ -1,14 +1,22 @@
 use std::{collections::HashMap, fs};
 
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::{
-    eyre::{bail, Context},
+    eyre::{bail, ensure, Context},
     Result,
 };
-use semver::Version;
+use colored::Colorize;
+use log::warn;
+use semver::{Version, VersionReq};
 use serde::Deserialize;
 
-use crate::Package;
+use crate::{package::Type, Package, Project};
+
+// The directories of the package directory searched for the spec, in order
+static SPEC_DIRS: &[&str] = &["", ".package", "packaging"];
+// The file names of the spec, without the extension
+static SPEC_NAMES: &[&str] = &["install", "rinstall"];
+static SPEC_EXTENSIONS: &[&str] = &["yml", "yaml"];
 
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -20,25 +28,185 @@ pub struct InstallSpec {
 }
 
 impl InstallSpec {
-    pub fn new_from_path(package_dir: &Utf8Path) -> Result<Self> {
-        // Try root/install.yml and root/.package/install.yml files
-        let install_spec = {
-            let install_spec = package_dir.join("install.yml");
-            if install_spec.exists() {
+    // Load the spec from --package-file if given, otherwise search it in the package directory
+    pub fn load(
+        package_dir: &Utf8Path,
+        package_file: Option<&Utf8Path>,
+    ) -> Result<Self> {
+        let mut install_spec = if let Some(package_file) = package_file {
+            ensure!(package_file.exists(), "{:?} does not exist", package_file);
+            Self::new_from_file(package_file)?
+        } else {
+            Self::new_from_path(package_dir)?
+        };
+        install_spec.infer_project_types(package_dir);
+        install_spec.infer_versions(package_dir);
+
+        Ok(install_spec)
+    }
+
+    // The packages without a type are rust packages when the package directory
+    // contains a cargo package (since 0.3.0), the type in the file always wins
+    fn infer_project_types(
+        &mut self,
+        package_dir: &Utf8Path,
+    ) {
+        if !VersionReq::parse(">=0.3.0").unwrap().matches(&self.version)
+            || !is_cargo_package(package_dir)
+        {
+            return;
+        }
+        // The files of release tarballs are all in the package directory anyway
+        let changes_outputdir = !Project::is_release_tarball(package_dir, false);
+        for (name, package) in &mut self.packages {
+            if package.project_type.is_some() {
+                continue;
+            }
+            if changes_outputdir {
+                warn!(
+                    "package {} has no type, treating it as {} because of Cargo.toml: the files \
+                     are searched in the cargo target directory first, add {} to keep them in \
+                     the project directory",
+                    name.bright_black().italic(),
+                    "type: rust".bright_black().italic(),
+                    "type: default".bright_black().italic()
+                );
+            }
+            package.project_type = Some(Type::Rust);
+        }
+    }
+
+    // The rust packages without a version take the one of the cargo package (since 0.3.0)
+    fn infer_versions(
+        &mut self,
+        package_dir: &Utf8Path,
+    ) {
+        if !VersionReq::parse(">=0.3.0").unwrap().matches(&self.version) {
+            return;
+        }
+        let Some(cargo_version) = cargo_package_version(package_dir) else {
+            return;
+        };
+        for package in self.packages.values_mut() {
+            if package.version.is_none() && package.project_type() == Type::Rust {
+                package.version = Some(cargo_version.clone());
+            }
+        }
+    }
+
+    // Parse a single spec file, TOML files are recognized by their extension,
+    // every other file is parsed as YAML (which JSON is a subset of)
+    fn new_from_file(file: &Utf8Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(file).with_context(|| format!("unable to read file {:?}", file))?;
+        if file.extension() == Some("toml") {
+            toml::from_str(&contents).with_context(|| format!("unable to parse file {:?}", file))
+        } else {
+            serde_yaml::from_str(&contents)
+                .with_context(|| format!("unable to parse file {:?}", file))
+        }
+    }
+
+    fn new_from_path(package_dir: &Utf8Path) -> Result<Self> {
+        // Search the spec in the directories below, in order, using the first one
+        // containing either one of the spec names or the install.d directory with
+        // the fragments to merge
+        let mut tried = Vec::new();
+        let mut found = None;
+        for dir in SPEC_DIRS {
+            let spec_dir = package_dir.join(dir);
+            let specs: Vec<Utf8PathBuf> = SPEC_NAMES
+                .iter()
+                .flat_map(|name| {
+                    SPEC_EXTENSIONS
+                        .iter()
+                        .map(move |extension| format!("{}.{}", name, extension))
+                })
+                .map(|name| spec_dir.join(name))
+                .inspect(|spec| tried.push(spec.to_string()))
+                .filter(|spec| spec.exists())
+                .collect();
+            ensure!(
+                specs.len() <= 1,
+                "found more than one install.yml in {:?}: {}, remove all but one of them",
+                spec_dir,
+                specs
+                    .iter()
+                    .map(|spec| spec.file_name().unwrap_or_default())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            let fragments_dir = spec_dir.join("install.d");
+            if !specs.is_empty() || fragments_dir.is_dir() {
+                found = Some((specs.into_iter().next(), fragments_dir));
+                break;
+            }
+            tried.push(format!("{}/", fragments_dir));
+        }
+        let Some((install_spec, fragments_dir)) = found else {
+            bail!(
+                "unable to find 'install.yml' file, tried:\n  {}",
+                tried.join("\n  ")
+            );
+        };
+
+        let mut files = Vec::new();
+        files.extend(install_spec);
+        if fragments_dir.is_dir() {
+            let mut fragments = fragments_dir
+                .read_dir_utf8()
+                .with_context(|| format!("unable to read directory {:?}", fragments_dir))?
+                .map(|entry| -> Result<Utf8PathBuf> { Ok(entry?.path().to_path_buf()) })
+                .collect::<Result<Vec<Utf8PathBuf>>>()?;
+            fragments.retain(|path| {
+                path.extension()
+                    .is_some_and(|extension| SPEC_EXTENSIONS.contains(&extension))
+            });
+            // Merge the fragments in a reproducible order
+            fragments.sort();
+            files.extend(fragments);
+        }
+        ensure!(
+            !files.is_empty(),
+            "the directory {:?} does not contain any fragment",
+            fragments_dir
+        );
+
+        let mut install_spec: Option<Self> = None;
+        for file in files {
+            let spec = Self::new_from_file(&file)?;
+            if let Some(install_spec) = &mut install_spec {
                 install_spec
+                    .merge(spec)
+                    .with_context(|| format!("unable to merge file {:?}", file))?;
             } else {
-                let install_spec = package_dir.join(".package").join("install.yml");
-                if install_spec.exists() {
-                    install_spec
-                } else {
-                    bail!("unable to find 'install.yml' file");
-                }
+                install_spec = Some(spec);
             }
-        };
-        Ok(serde_yaml::from_str(
-            &fs::read_to_string(&install_spec)
-                .with_context(|| format!("unable to read file {:?}", install_spec))?,
-        )?)
+        }
+
+        Ok(install_spec.unwrap())
+    }
+
+    fn merge(
+        &mut self,
+        other: Self,
+    ) -> Result<()> {
+        ensure!(
+            self.version == other.version,
+            "all the files must use the same rinstall version, found {} and {}",
+            self.version,
+            other.version
+        );
+        for (name, package) in other.packages {
+            if let Some(existing) = self.packages.get_mut(&name) {
+                existing.name = Some(name);
+                existing.merge(package)?;
+            } else {
+                self.packages.insert(name, package);
+            }
+        }
+
+        Ok(())
     }
 
     pub fn packages(
@@ -56,3 +224,23 @@ impl InstallSpec {
             .collect::<Vec<Package>>()
     }
 }
+
+fn cargo_manifest(package_dir: &Utf8Path) -> Option<toml::Value> {
+    fs::read_to_string(package_dir.join("Cargo.toml"))
+        .ok()
+        .and_then(|contents| toml::from_str(&contents).ok())
+}
+
+// Whether the directory contains a Cargo.toml with a [package], and not only a workspace
+fn is_cargo_package(package_dir: &Utf8Path) -> bool {
+    cargo_manifest(package_dir).is_some_and(|manifest| manifest.get("package").is_some())
+}
+
+// The version of the cargo package, None when it is inherited from the workspace
+fn cargo_package_version(package_dir: &Utf8Path) -> Option<String> {
+    cargo_manifest(package_dir)?
+        .get("package")?
+        .get("version")?
+        .as_str()
+        .map(str::to_string)
+}
@@ -1,16 +1,46 @@
+use std::{collections::BTreeMap, process::Command};
+
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::{
-    eyre::{ensure, ContextCompat},
+    eyre::{bail, ensure, Context, ContextCompat},
     Result,
 };
+use walkdir::WalkDir;
 
-use crate::install_entry::InstallEntry;
+use crate::{
+    compression::split_compressed_extension,
+    install_cmd_impl::ManCompression,
+    install_entry::InstallEntry,
+    post_install::{LoadedPolicy, PostInstall},
+    project::Project,
+    templating::TemplatingMode,
+};
 
 pub struct InstallTarget {
     pub source: Utf8PathBuf,
     pub destination: Utf8PathBuf,
-    pub templating: bool,
+    pub templating: TemplatingMode,
     pub replace: bool,
+    // The section of install.yml containing the entry, e.g. exe
+    pub section: &'static str,
+    pub post_install: Option<PostInstall>,
+    // Compress the file when installing it, the extension is appended to the destination
+    pub compression: ManCompression,
+    // Decompress the source when installing it, the compression extension is
+    // removed from the destination
+    pub decompress: bool,
+    // The files of a directory installed with a different name
+    pub rename: BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+    // Symlinks to the installed file, created in the same directory
+    pub aliases: Vec<Utf8PathBuf>,
+    // Run the source with these arguments and install its output instead
+    pub generate_args: Option<Vec<String>>,
+    // Install the files with these permissions instead of the ones from --file-mode
+    pub mode: Option<u32>,
+    // The owner of the installed files, as user[:group]
+    pub owner: Option<String>,
+    // The template variables of the entry, on top of the directories
+    pub vars: BTreeMap<String, String>,
 }
 
 #[derive(Clone, Copy)]
@@ -24,6 +54,7 @@ impl InstallTarget {
         entry: InstallEntry,
         install_dir: &Utf8Path,
         policy: FilesPolicy,
+        section: &'static str,
     ) -> Result<Self> {
         let replace = matches!(policy, FilesPolicy::Replace);
         ensure!(
@@ -32,28 +63,216 @@ impl InstallTarget {
             entry.source
         );
 
-        let destination =
-            if entry.source.is_file() || entry.destination.is_some() {
-                install_dir.join(if let Some(destination) = entry.destination {
-                    ensure!(
-                        destination.is_relative(),
-                        "the destination part of a file must be relative"
-                    );
-                    destination
-                } else {
-                    Utf8PathBuf::from(entry.source.file_name().with_context(|| {
-                        format!("unable to get file name from {:?}", entry.source)
-                    })?)
-                })
-            } else {
-                install_dir.to_path_buf()
-            };
+        for (from, to) in &entry.rename {
+            ensure!(
+                from.is_relative() && to.is_relative(),
+                "the files renamed in {:?} must be relative, found {:?} -> {:?}",
+                entry.source,
+                from,
+                to
+            );
+        }
+
+        ensure!(
+            entry.vars.is_empty()
+                || entry
+                    .templating
+                    .is_some_and(|templating| templating != TemplatingMode::Disabled),
+            "the vars of {:?} are only used for templating, enable it with tmpl",
+            entry.source
+        );
+
+        let destination = if let Some(destination) = entry.destination {
+            // An absolute destination would escape the directory of the section
+            ensure!(
+                destination.is_relative(),
+                "the destination {:?} of {:?} must be relative to the {} directory {:?}, e.g. {:?}",
+                destination,
+                entry.source,
+                section,
+                install_dir,
+                destination.as_str().trim_start_matches('/')
+            );
+            install_dir.join(destination)
+        } else {
+            // Mark the destination as a directory, the name of the file
+            // will be appended when listing the files to install
+            install_dir.join("")
+        };
 
         Ok(Self {
             source: entry.source,
             destination,
-            templating: entry.templating,
+            templating: entry.templating.unwrap_or_default(),
             replace,
+            section,
+            post_install: None,
+            compression: ManCompression::None,
+            decompress: false,
+            rename: entry.rename,
+            aliases: Vec::new(),
+            generate_args: None,
+            mode: None,
+            owner: None,
+            vars: entry.vars,
+        })
+    }
+
+    // Return the paths of the symlinks pointing to an installed file,
+    // using the same compression extension of the file, e.g. foo.1.gz
+    pub fn alias_paths(
+        &self,
+        destination: &Utf8Path,
+    ) -> Vec<Utf8PathBuf> {
+        let (_, compression) = split_compressed_extension(destination.as_str());
+        let parent = destination.parent().unwrap_or(destination);
+        self.aliases
+            .iter()
+            .map(|alias| {
+                compression.map_or_else(
+                    || parent.join(alias),
+                    |extension| parent.join(format!("{}.{}", alias, extension)),
+                )
+            })
+            .collect()
+    }
+
+    // The files of these sections are programs, installed as executables
+    // even when the source is not
+    pub fn is_executable(&self) -> bool {
+        matches!(self.section, "exe" | "admin-exe" | "libexec")
+    }
+
+    // Run the source of a generated file and return its output
+    pub fn generate(
+        &self,
+        source: &Utf8Path,
+        project: &Project,
+    ) -> Result<Vec<u8>> {
+        let args = self.generate_args.as_deref().unwrap_or_default();
+        let command = format!("{} {}", source, args.join(" "));
+        // The source is relative to the current directory when the package
+        // directory is, it would be resolved again from the project directory
+        let program = source
+            .canonicalize_utf8()
+            .with_context(|| format!("unable to resolve {:?}", source))?;
+        let output = Command::new(program)
+            .args(args)
+            .current_dir(&project.projectdir)
+            .output()
+            .with_context(|| format!("unable to run `{}`", command))?;
+        ensure!(
+            output.status.success(),
+            "`{}` failed with {}:\n{}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+
+        Ok(output.stdout)
+    }
+
+    // The commands to run after installing the target. The policies are loaded one
+    // installed file at a time, as the files of a directory are installed into
+    // the destination directly
+    pub fn post_install_hooks(
+        &self,
+        project: &Project,
+    ) -> Result<Vec<PostInstall>> {
+        Ok(match &self.post_install {
+            Some(PostInstall::LoadPolicy(policy)) => self
+                .files(project)?
+                .into_iter()
+                .map(|(_, path)| {
+                    PostInstall::LoadPolicy(LoadedPolicy {
+                        kind: policy.kind,
+                        path,
+                    })
+                })
+                .collect(),
+            Some(hook) => vec![hook.clone()],
+            None => Vec::new(),
         })
     }
+
+    // Return the list of files to install as (source, destination) pairs
+    pub fn files(
+        &self,
+        project: &Project,
+    ) -> Result<Vec<(Utf8PathBuf, Utf8PathBuf)>> {
+        let source = project.source_path(&self.source);
+        if let Some(outputdir) = project.missing_outputdir() {
+            ensure!(
+                source.exists(),
+                "{:?} does not exist, the output directory {:?} is missing as the project has not \
+                 been built",
+                source,
+                outputdir
+            );
+        }
+        ensure!(source.exists(), "{:?} does not exist", source);
+
+        ensure!(
+            self.generate_args.is_none() || source.is_file(),
+            "the source {:?} must be an executable to generate the file",
+            source
+        );
+        if source.is_file() {
+            ensure!(
+                self.rename.is_empty(),
+                "rename can only be used when the source {:?} is a directory",
+                source
+            );
+            let destination = if self.destination.as_str().ends_with('/') {
+                self.destination.join(
+                    source
+                        .file_name()
+                        .with_context(|| format!("unable to get filename for {:?}", source))?,
+                )
+            } else {
+                self.destination.clone()
+            };
+            let destination = if self.decompress {
+                Utf8PathBuf::from(split_compressed_extension(destination.as_str()).0)
+            } else {
+                destination
+            };
+            let destination = if let Some(extension) = self.compression.extension() {
+                Utf8PathBuf::from(format!("{}.{}", destination, extension))
+            } else {
+                destination
+            };
+            Ok(vec![(source, destination)])
+        } else if source.is_dir() {
+            for from in self.rename.keys() {
+                ensure!(
+                    source.join(from).is_file(),
+                    "the renamed file {:?} does not exist in {:?}",
+                    from,
+                    source
+                );
+            }
+            WalkDir::new(&source)
+                .into_iter()
+                .filter_map(|entry| match entry {
+                    Ok(entry) if !entry.file_type().is_file() => None,
+                    entry => Some(entry),
+                })
+                .map(|entry| -> Result<(Utf8PathBuf, Utf8PathBuf)> {
+                    let entry = entry?;
+                    let full_path = Utf8Path::from_path(entry.path()).unwrap();
+                    let relative_path = full_path.strip_prefix(&source).with_context(|| {
+                        format!("unable to strip prefix {:?} from {:?}", source, full_path)
+                    })?;
+                    let destination = self.rename.get(relative_path).map_or_else(
+                        || self.destination.join(relative_path),
+                        |name| self.destination.join(name),
+                    );
+                    Ok((source.join(relative_path), destination))
+                })
+                .collect()
+        } else {
+            bail!("{:?} is neither a file nor a directory", source);
+        }
+    }
 }
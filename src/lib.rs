@@ -0,0 +1,80 @@
+//! rinstall installs the files of a project as described by its install.yml.
+//!
+//! The binary is a thin wrapper around this library; other tools can reuse
+//! the computation of the files to install and the installation itself:
+//!
+//! ```no_run
+//! use clap::Parser;
+//! use rinstall::{Opts, SubCommand};
+//!
+//! let SubCommand::Install(opts) = Opts::parse_from(["rinstall", "install", "--system"]).subcmd
+//! else {
+//!     unreachable!()
+//! };
+//! let targets = rinstall::compute_targets(&opts)?;
+//! let report = rinstall::execute(targets, *opts)?;
+//! println!("{}", report);
+//! # Ok::<(), color_eyre::Report>(())
+//! ```
+
+mod appstream;
+mod completion;
+mod completions_impl;
+mod compression;
+mod custom_entry;
+mod dirs;
+mod dirs_config_impl;
+mod icon;
+mod install_cmd_impl;
+mod install_entry;
+mod install_spec;
+mod install_target;
+mod list_impl;
+mod man_page;
+mod opts_impl;
+mod package;
+mod package_info;
+mod post_install;
+mod print_dirs_impl;
+mod project;
+mod rpm_files_impl;
+mod simple_logger;
+mod systemd_unit;
+mod templating;
+mod uninstall_impl;
+mod utils;
+
+#[macro_use]
+extern crate lazy_static;
+
+use color_eyre::Result;
+
+pub use completions_impl::GenerateCompletions;
+pub use dirs::Dirs;
+pub use dirs_config_impl::DirsConfig;
+pub use install_cmd_impl::{InstallCmd, PackageTargets, Report, SkipReason, TargetError};
+pub use install_spec::InstallSpec;
+pub use install_target::InstallTarget;
+pub use list_impl::List;
+pub use opts_impl::{Opts, OutputFormat, SubCommand};
+pub use package::{Package, TargetOptions};
+pub use print_dirs_impl::PrintDirs;
+pub use project::Project;
+pub use rpm_files_impl::GenerateRpmFiles;
+pub use simple_logger::SimpleLogger;
+pub use uninstall_impl::Uninstall;
+
+/// Compute the files to install for the packages selected by `opts`, without
+/// changing anything on the filesystem
+pub fn compute_targets(opts: &InstallCmd) -> Result<Vec<PackageTargets>> {
+    opts.compute_targets()
+}
+
+/// Install the targets computed by [`compute_targets`] and return the files that
+/// have been installed, left unchanged or skipped and the entries that failed
+pub fn execute(
+    targets: Vec<PackageTargets>,
+    opts: InstallCmd,
+) -> Result<Report> {
+    opts.execute(targets)
+}
@@ -0,0 +1,29 @@
+#[derive(Parser, Clone)]
+pub struct List {
+    #[clap(help = "Path to the rinstall.yml configuration", from_global)]
+    pub config: Option<String>,
+    #[clap(long = "system", help = "List the packages installed system-wide")]
+    pub system: bool,
+    #[clap(
+        long = "user",
+        help = "List the packages installed for the current user (the default)",
+        conflicts_with = "system"
+    )]
+    pub user: bool,
+    #[clap(
+        long,
+        env,
+        requires = "system",
+        help = concat!("The prefix used when installing the packages. (system only)",
+                       " [default: /usr/local]")
+    )]
+    pub prefix: Option<String>,
+    #[clap(
+        long,
+        env,
+        help = "The localstatedir used when installing the packages"
+    )]
+    pub localstatedir: Option<String>,
+    #[clap(help = "The name or pkginfo file of a package to list the installed files of")]
+    pub package: Option<String>,
+}
@@ -0,0 +1,110 @@
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use color_eyre::{
+    eyre::{ensure, Context},
+    Result,
+};
+use colored::Colorize;
+
+use crate::{dirs::Dirs, dirs_config_impl::DirsConfig, package_info::PackageInfo};
+
+include!("list.rs");
+
+impl List {
+    pub fn run(&self) -> Result<()> {
+        let opt_dirs = DirsConfig {
+            prefix: self.prefix.clone(),
+            localstatedir: self.localstatedir.clone(),
+            ..Default::default()
+        };
+        let dirs_config = DirsConfig::load(self.config.as_deref(), self.system, None, &opt_dirs)?;
+        let dirs = Dirs::new(dirs_config, self.system).context("unable to create dirs")?;
+        let pkg_info_dir = PackageInfo::dir(&dirs);
+
+        if let Some(pkg) = &self.package {
+            let pkg_info = if Utf8Path::new(pkg).is_absolute() {
+                Utf8PathBuf::from(pkg)
+            } else {
+                pkg_info_dir.join(format!("{}.pkg", pkg))
+            };
+            ensure!(pkg_info.exists(), "package {} is not installed", pkg);
+            let pkg_info = PackageInfo::load(&pkg_info)?;
+            if let Some(metadata) = &pkg_info.metadata {
+                if let Some(version) = &metadata.version {
+                    println!("{} {}", "Version".bright_black(), version.italic());
+                }
+                println!(
+                    "{} {} {} {} {} {}",
+                    "Installed".bright_black(),
+                    if metadata.system {
+                        "system-wide"
+                    } else {
+                        "for the user"
+                    }
+                    .bright_black(),
+                    "on".bright_black(),
+                    metadata.installed_at.italic(),
+                    "by rinstall".bright_black(),
+                    metadata.rinstall_version.italic(),
+                );
+            }
+            for file in &pkg_info.files {
+                if file.replace {
+                    println!("{}", file.path.as_str().cyan().bold());
+                } else {
+                    println!(
+                        "{} {}",
+                        file.path.as_str().cyan().bold(),
+                        "(config)".bright_black()
+                    );
+                }
+            }
+
+            return Ok(());
+        }
+
+        if !pkg_info_dir.exists() {
+            return Ok(());
+        }
+        let mut pkg_infos = Vec::new();
+        for entry in fs::read_dir(&pkg_info_dir)
+            .with_context(|| format!("unable to read directory {:?}", pkg_info_dir))?
+        {
+            let path = entry
+                .with_context(|| format!("unable to read directory {:?}", pkg_info_dir))?
+                .path();
+            let path = Utf8PathBuf::try_from(path)
+                .with_context(|| format!("invalid file found in {:?}", pkg_info_dir))?;
+            if path.extension() == Some("pkg") {
+                pkg_infos.push(path);
+            }
+        }
+        pkg_infos.sort();
+
+        for pkg_info in pkg_infos {
+            let pkg_info = PackageInfo::load(&pkg_info)?;
+            let version = pkg_info
+                .metadata
+                .as_ref()
+                .and_then(|metadata| metadata.version.as_deref());
+            if let Some(version) = version {
+                println!(
+                    "{} {} ({} files)",
+                    pkg_info.pkg_name.italic().blue(),
+                    version,
+                    pkg_info.files.len()
+                );
+            } else {
+                println!(
+                    "{} ({} files)",
+                    pkg_info.pkg_name.italic().blue(),
+                    pkg_info.files.len()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,46 +1,60 @@
-mod dirs;
-mod dirs_config_impl;
-mod icon;
-mod install_cmd_impl;
-mod install_entry;
-mod install_spec;
-mod install_target;
-mod opts_impl;
-mod package;
-mod package_info;
-mod project;
-mod simple_logger;
-mod templating;
-mod uninstall_impl;
-mod utils;
-
-#[macro_use]
-extern crate lazy_static;
-
 use clap::Parser;
-use color_eyre::Result;
+use color_eyre::{Report, Result};
 use log::LevelFilter;
 
-use dirs::Dirs;
-pub use dirs_config_impl::DirsConfig;
-pub use install_cmd_impl::InstallCmd;
-pub use opts_impl::{Opts, SubCommand};
-use package::Package;
-use simple_logger::SimpleLogger;
-pub use uninstall_impl::Uninstall;
+use rinstall::{Opts, OutputFormat, SimpleLogger, SubCommand, TargetError};
 
 fn main() -> Result<()> {
     color_eyre::install()?;
     let opts = Opts::parse();
-    log::set_boxed_logger(Box::new(SimpleLogger { quiet: opts.quiet }))
-        .map(|()| log::set_max_level(LevelFilter::Info))?;
+    if opts.format == OutputFormat::Json {
+        colored::control::set_override(false);
+    }
+    log::set_boxed_logger(Box::new(SimpleLogger {
+        quiet: opts.quiet,
+        verbose: opts.verbose,
+        format: opts.format,
+    }))
+    .map(|()| {
+        log::set_max_level(if opts.verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        })
+    })?;
 
-    match opts.subcmd {
-        SubCommand::Uninstall(uninstall) => {
-            uninstall.run()?;
+    let result = match opts.subcmd {
+        SubCommand::Uninstall(uninstall) => uninstall.run(),
+        SubCommand::Install(install) => install.run(),
+        SubCommand::List(list) => list.run(),
+        SubCommand::RpmFiles(rpm_files) => rpm_files.run(),
+        SubCommand::Completions(completions) => completions.run(),
+        SubCommand::PrintDirs(print_dirs) => print_dirs.run(),
+    };
+    match result {
+        Err(err) if opts.format == OutputFormat::Json => {
+            eprintln!("{}", json_error(&err));
+            std::process::exit(1);
         }
-        SubCommand::Install(install) => install.run()?,
+        result => result,
+    }
+}
+
+// Serialize an error as {"error": ..., "context": [...], "target": ...}, where
+// context contains the messages wrapping the error, starting from the outermost
+fn json_error(err: &Report) -> String {
+    let mut messages = err
+        .chain()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>();
+    let error = messages.pop().unwrap_or_default();
+    let mut object = json::object! {
+        error: error,
+        context: messages,
+    };
+    if let Some(target_error) = err.downcast_ref::<TargetError>() {
+        object["target"] = target_error.target.as_str().into();
     }
 
-    Ok(())
+    object.dump()
 }
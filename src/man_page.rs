@@ -0,0 +1,210 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{ensure, ContextCompat},
+    Result,
+};
+use serde::{Deserialize, Deserializer};
+use void::Void;
+
+use crate::{
+    compression::split_compressed_extension, install_entry::InstallEntry,
+    templating::TemplatingMode,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManPage {
+    #[serde(rename(deserialize = "src"))]
+    pub source: Utf8PathBuf,
+    #[serde(rename(deserialize = "dst"))]
+    pub destination: Option<Utf8PathBuf>,
+    #[serde(default, rename(deserialize = "tmpl"))]
+    pub templating: Option<TemplatingMode>,
+    pub locale: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_section")]
+    pub section: Option<String>,
+    // Other names of the man page, installed as symlinks to it
+    #[serde(default)]
+    pub aliases: Vec<Utf8PathBuf>,
+    // Only install the entry when this feature is enabled with --features
+    #[serde(default)]
+    pub when: Option<String>,
+    // Template variables of this entry only, overriding the directories
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+}
+
+// Allow writing the section both as a number (1) and as a string ("3p")
+fn deserialize_section<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Section {
+        Number(u32),
+        Name(String),
+    }
+
+    Ok(
+        Option::<Section>::deserialize(deserializer)?.map(|section| match section {
+            Section::Number(number) => number.to_string(),
+            Section::Name(name) => name,
+        }),
+    )
+}
+
+impl ManPage {
+    const fn new_with_source(source: Utf8PathBuf) -> Self {
+        Self {
+            source,
+            destination: None,
+            templating: None,
+            locale: None,
+            section: None,
+            aliases: Vec::new(),
+            when: None,
+            vars: BTreeMap::new(),
+        }
+    }
+
+    // Get the names of the aliases, installed in the same directory of the man page.
+    // The section is appended to the aliases without one, e.g. gunzip -> gunzip.1
+    pub fn alias_names(
+        &self,
+        install_dir: &Utf8Path,
+    ) -> Result<Vec<Utf8PathBuf>> {
+        let section = install_dir
+            .file_name()
+            .and_then(|name| name.strip_prefix("man"))
+            .unwrap_or_default();
+        self.aliases
+            .iter()
+            .map(|alias| {
+                ensure!(
+                    alias.components().count() == 1 && alias.file_name().is_some(),
+                    "invalid alias {:?} for man page {:?}, it should be a file name",
+                    alias,
+                    self.source
+                );
+                Ok(if alias.extension().is_some() {
+                    alias.clone()
+                } else {
+                    Utf8PathBuf::from(format!("{}.{}", alias, section))
+                })
+            })
+            .collect()
+    }
+
+    // The compression extension of the source, e.g. gz for foo.1.gz
+    pub fn compression(&self) -> Option<&str> {
+        split_compressed_extension(self.source.as_str()).1
+    }
+
+    // Get the directory where the man page will be installed, e.g. man1 or
+    // de/man1 for translated man pages
+    pub fn install_dir(
+        &self,
+        mandir: &Utf8Path,
+    ) -> Result<Utf8PathBuf> {
+        ensure!(
+            !self.source.as_str().ends_with('/'),
+            "the man entry cannot be a directory"
+        );
+        let man_cat = if let Some(section) = &self.section {
+            ensure!(
+                section.chars().next().is_some_and(|c| c.is_ascii_digit())
+                    && !section.contains('/'),
+                "invalid section '{}' for man page {:?}, it should start with a digit from 1 to 8",
+                section,
+                self.source
+            );
+            section.clone()
+        } else {
+            let use_source_name = self
+                .destination
+                .as_ref()
+                .is_none_or(|destination| destination.as_str().ends_with('/'));
+            let name = if use_source_name {
+                &self.source
+            } else {
+                self.destination.as_ref().unwrap()
+            };
+            // Ignore the compression extension, e.g. foo.1.gz
+            let (name, _) = split_compressed_extension(name.as_str());
+            let man_cat = Utf8Path::new(name)
+                .extension()
+                .with_context(|| format!("unable to get extension of file {:?}", name))?
+                .to_string();
+            ensure!(
+                man_cat.chars().next().unwrap().is_ascii_digit(),
+                "the last character should be a digit from 1 to 8"
+            );
+            man_cat
+        };
+
+        let mandir = if let Some(locale) = &self.locale {
+            ensure!(
+                !locale.is_empty() && !locale.contains('/') && locale != "." && locale != "..",
+                "invalid locale '{}' for man page {:?}",
+                locale,
+                self.source
+            );
+            mandir.join(locale)
+        } else {
+            mandir.to_path_buf()
+        };
+
+        Ok(mandir.join(format!("man{}", &man_cat)))
+    }
+}
+
+impl From<ManPage> for InstallEntry {
+    fn from(man_page: ManPage) -> Self {
+        // When the section is set, the source name does not contain it, e.g. foo.man,
+        // so rename the man page to foo.<section>
+        let destination = match (&man_page.section, man_page.destination) {
+            (Some(section), destination)
+                if destination
+                    .as_ref()
+                    .is_none_or(|destination| destination.as_str().ends_with('/')) =>
+            {
+                let file_name = man_page.source.file_name().unwrap_or_default();
+                let (file_name, compression) = split_compressed_extension(file_name);
+                let mut name = format!(
+                    "{}.{}",
+                    Utf8Path::new(file_name).file_stem().unwrap_or_default(),
+                    section
+                );
+                if let Some(compression) = compression {
+                    name.push('.');
+                    name.push_str(compression);
+                }
+                Some(destination.map_or(Utf8PathBuf::from(&name), |destination| {
+                    destination.join(&name)
+                }))
+            }
+            (_, destination) => destination,
+        };
+        Self {
+            source: man_page.source,
+            destination,
+            templating: man_page.templating,
+            rename: BTreeMap::new(),
+            when: man_page.when,
+            vars: man_page.vars,
+        }
+    }
+}
+
+impl FromStr for ManPage {
+    // This implementation of `from_str` can never fail, so use the impossible
+    // `Void` type as the error type.
+    type Err = Void;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new_with_source(Utf8PathBuf::from(s)))
+    }
+}
@@ -17,17 +17,54 @@ pub struct Opts {
         short,
         long,
         help = concat!("Do not print anything on the stdout. Warnings and",
-                       " errors will still be print on the stderr")
+                       " errors will still be print on the stderr"),
+        global = true
     )]
     pub quiet: bool,
+    #[clap(
+        short,
+        long,
+        help = "Print every file installed instead of a summary",
+        conflicts_with = "quiet",
+        global = true
+    )]
+    pub verbose: bool,
+    #[clap(
+        long,
+        help = "Print the messages and the errors as JSON lines, for tools wrapping rinstall",
+        value_enum,
+        default_value = "human",
+        global = true
+    )]
+    pub format: OutputFormat,
     #[clap(subcommand)]
     pub subcmd: SubCommand,
 }
 
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
 #[derive(Subcommand)]
 pub enum SubCommand {
     #[clap(about = "Install the packages into the system")]
     Install(Box<InstallCmd>),
     #[clap(about = "Uninstall the packages from the system")]
     Uninstall(Uninstall),
+    #[clap(about = "List the installed packages")]
+    List(List),
+    #[clap(
+        name = "rpm-files",
+        about = "Print the list of installed files for the %files section of a RPM spec file"
+    )]
+    RpmFiles(Box<GenerateRpmFiles>),
+    #[clap(about = "Print the completion script of rinstall for the given shell")]
+    Completions(GenerateCompletions),
+    #[clap(
+        name = "print-dirs",
+        about = "Print the installation directories after replacing the placeholders"
+    )]
+    PrintDirs(Box<PrintDirs>),
 }
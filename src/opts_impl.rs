@@ -1,6 +1,10 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
+use crate::GenerateCompletions;
+use crate::GenerateRpmFiles;
 use crate::InstallCmd;
+use crate::List;
+use crate::PrintDirs;
 use crate::Uninstall;
 
 include!("opts.rs");
@@ -1,6 +1,8 @@
+use std::{collections::BTreeMap, process::Command, str::FromStr};
+
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::{
-    eyre::{ensure, Context, ContextCompat},
+    eyre::{bail, ensure, Context, ContextCompat},
     Result,
 };
 use colored::Colorize;
@@ -8,14 +10,34 @@ use log::warn;
 use semver::{Version, VersionReq};
 use serde::Deserialize;
 
+use crate::completion::Completion;
+use crate::custom_entry::CustomEntry;
+use crate::install_cmd_impl::ManCompression;
 use crate::install_entry::{string_or_struct, InstallEntry};
 use crate::install_target::InstallTarget;
-use crate::Dirs;
+use crate::man_page::ManPage;
+use crate::post_install::{EnabledUnit, LoadedPolicy, PolicyKind, PostInstall, UnitScope};
+use crate::project::Project;
+use crate::systemd_unit::SystemdUnit;
+use crate::templating::TemplatingMode;
 use crate::{icon::Icon, install_target::FilesPolicy};
+use crate::{Dirs, DirsConfig};
+
+// The versions of install.yml that can be installed, newer patch versions
+// are accepted as they do not add any new key
+static SUPPORTED_VERSIONS: &str = ">=0.1.0, <0.4.0";
+
+static FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc"];
 
-#[derive(Deserialize, Clone, PartialEq, Debug)]
+static KERNEL_MODULE_EXTENSIONS: &[&str] = &["ko", "ko.gz", "ko.xz", "ko.zst"];
+
+// The kernel only loads the modules from here, whatever the prefix
+static KERNEL_MODULES_DIR: &str = "/usr/lib/modules";
+
+#[derive(Deserialize, Clone, PartialEq, Debug, Default)]
 pub enum Type {
     #[serde(rename(deserialize = "default"))]
+    #[default]
     Default,
     #[serde(rename(deserialize = "rust"))]
     Rust,
@@ -23,12 +45,6 @@ pub enum Type {
     Custom,
 }
 
-impl Default for Type {
-    fn default() -> Self {
-        Self::Default
-    }
-}
-
 #[derive(Deserialize)]
 #[serde(untagged)]
 enum Entry {
@@ -43,25 +59,170 @@ enum IconEntry {
     Icon(Icon),
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManEntry {
+    #[serde(deserialize_with = "string_or_struct")]
+    ManPage(ManPage),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SystemdUnitEntry {
+    #[serde(deserialize_with = "string_or_struct")]
+    SystemdUnit(SystemdUnit),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CompletionEntry {
+    #[serde(deserialize_with = "string_or_struct")]
+    Completion(Completion),
+}
+
 #[derive(Deserialize, Default)]
 #[serde(deny_unknown_fields)]
 struct Completions {
     #[serde(default)]
-    pub bash: Vec<Entry>,
+    pub bash: Vec<CompletionEntry>,
+    #[serde(default)]
+    pub elvish: Vec<CompletionEntry>,
     #[serde(default)]
-    pub elvish: Vec<Entry>,
+    pub fish: Vec<CompletionEntry>,
+    #[serde(default)]
+    pub zsh: Vec<CompletionEntry>,
+    // Directories whose files are installed for the shell matching their name
+    #[serde(default)]
+    pub auto: Vec<Utf8PathBuf>,
+}
+
+// The sourceable shell libraries, e.g. the zsh autoload functions
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ShellFunctions {
+    #[serde(default)]
+    pub bash: Vec<Entry>,
     #[serde(default)]
     pub fish: Vec<Entry>,
     #[serde(default)]
     pub zsh: Vec<Entry>,
 }
 
+impl Completions {
+    fn iter(&self) -> impl Iterator<Item = &Completion> {
+        self.bash
+            .iter()
+            .chain(&self.elvish)
+            .chain(&self.fish)
+            .chain(&self.zsh)
+            .map(|CompletionEntry::Completion(completion)| completion)
+    }
+
+    // Add the files contained in the auto directories to the completions of
+    // the respective shell: *.bash for bash, *.elv for elvish, *.fish for fish
+    // and _* or *.zsh for zsh, the latter installed as _*
+    fn route_auto_dirs(
+        &mut self,
+        project: &Project,
+    ) -> Result<()> {
+        for dir in std::mem::take(&mut self.auto) {
+            let path = project.source_path(&dir);
+            ensure!(
+                path.is_dir(),
+                "completions directory {:?} does not exist",
+                dir
+            );
+            let mut files = path
+                .read_dir_utf8()
+                .with_context(|| format!("unable to read directory {:?}", path))?
+                .map(|entry| -> Result<_> {
+                    let entry =
+                        entry.with_context(|| format!("unable to read directory {:?}", path))?;
+                    Ok((entry.file_name().to_string(), entry.path().is_file()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            files.sort();
+
+            for (file_name, is_file) in files {
+                if !is_file {
+                    continue;
+                }
+                let extension = Utf8Path::new(&file_name).extension();
+                let shells = [
+                    (extension == Some("bash"), &mut self.bash),
+                    (extension == Some("elv"), &mut self.elvish),
+                    (extension == Some("fish"), &mut self.fish),
+                    (
+                        file_name.starts_with('_') || extension == Some("zsh"),
+                        &mut self.zsh,
+                    ),
+                ];
+                let mut matching = shells
+                    .into_iter()
+                    .filter_map(|(matches, entries)| matches.then_some(entries))
+                    .collect::<Vec<_>>();
+                if matching.len() != 1 {
+                    warn!(
+                        "unable to detect the shell of the completion file {}, skipping it",
+                        dir.join(&file_name).as_str().yellow().bold()
+                    );
+                    continue;
+                }
+                let mut completion = Completion::from_str(dir.join(&file_name).as_str()).unwrap();
+                // zsh only autoloads the files named _<command>
+                if extension == Some("zsh") {
+                    completion.command = Utf8Path::new(&file_name).file_stem().map(str::to_string);
+                }
+                matching[0].push(CompletionEntry::Completion(completion));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The options given on the command line that change where the files are installed
+pub struct TargetOptions {
+    pub system_install: bool,
+    // None when the man pages are installed as they are
+    pub man_compression: Option<ManCompression>,
+    pub enable_units: bool,
+    // Refresh the whatis database after installing the man pages
+    pub update_mandb: bool,
+    // Install the libexec files into libexecdir/<package-name>
+    pub libexec_subdir: bool,
+    // Only install the given sections, e.g. exe
+    pub only: Vec<String>,
+    // Do not install the given sections
+    pub skip: Vec<String>,
+    // The features enabled for the entries with a when condition
+    pub features: Vec<String>,
+    // The kernel version of the modules directory, uname -r when not set
+    pub kernel_version: Option<String>,
+    // The owner of the installed polkit rules, as user[:group]
+    pub polkit_owner: Option<String>,
+    // Load the installed AppArmor profiles with apparmor_parser
+    pub load_apparmor: bool,
+    // Load the installed SELinux modules with semodule
+    pub load_selinux: bool,
+}
+
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Package {
     pub name: Option<String>,
+    // None when not set, see InstallSpec::infer_project_types
     #[serde(rename(deserialize = "type"), default)]
-    pub project_type: Type,
+    pub project_type: Option<Type>,
+    // The version of the package, recorded in the pkginfo. Taken from
+    // Cargo.toml for rust packages when not set
+    #[serde(default)]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub dirs: Option<DirsConfig>,
+    // The default value of tmpl for the entries of the package
+    #[serde(default)]
+    templating: Option<TemplatingMode>,
     #[serde(default)]
     exe: Vec<Entry>,
     #[serde(default, rename(deserialize = "admin-exe"))]
@@ -73,7 +234,7 @@ pub struct Package {
     #[serde(default)]
     includes: Vec<Entry>,
     #[serde(default)]
-    man: Vec<Entry>,
+    man: Vec<ManEntry>,
     #[serde(default)]
     data: Vec<Entry>,
     #[serde(default)]
@@ -84,61 +245,379 @@ pub struct Package {
     user_config: Vec<Entry>,
     #[serde(default, rename(deserialize = "desktop-files"))]
     desktop_files: Vec<Entry>,
+    #[serde(default)]
+    autostart: Vec<Entry>,
+    #[serde(default)]
+    environment: Vec<Entry>,
+    #[serde(default)]
+    profile: Vec<Entry>,
     #[serde(default, rename(deserialize = "appstream-metadata"))]
     appstream_metadata: Vec<Entry>,
+    #[serde(default, rename(deserialize = "kde-services"))]
+    kde_services: Vec<Entry>,
+    #[serde(default, rename(deserialize = "qt-plugins"))]
+    qt_plugins: Vec<Entry>,
     #[serde(default)]
     completions: Completions,
+    #[serde(default, rename(deserialize = "shell-functions"))]
+    shell_functions: ShellFunctions,
     #[serde(default, rename(deserialize = "pam-modules"))]
     pam_modules: Vec<Entry>,
     #[serde(default, rename(deserialize = "systemd-units"))]
-    systemd_units: Vec<Entry>,
+    systemd_units: Vec<SystemdUnitEntry>,
     #[serde(default, rename(deserialize = "systemd-user-units"))]
-    systemd_user_units: Vec<Entry>,
+    systemd_user_units: Vec<SystemdUnitEntry>,
+    #[serde(default, rename(deserialize = "systemd-preset"))]
+    systemd_preset: Vec<Entry>,
+    #[serde(default, rename(deserialize = "modules-load"))]
+    modules_load: Vec<Entry>,
+    #[serde(default)]
+    binfmt: Vec<Entry>,
     #[serde(default)]
     icons: Vec<IconEntry>,
     #[serde(default)]
+    fonts: Vec<Entry>,
+    #[serde(default, rename(deserialize = "kernel-modules"))]
+    kernel_modules: Vec<Entry>,
+    #[serde(default, rename(deserialize = "polkit-rules"))]
+    polkit_rules: Vec<Entry>,
+    #[serde(default)]
+    mime: Vec<Entry>,
+    #[serde(default)]
     terminfo: Vec<Entry>,
     #[serde(default)]
     licenses: Vec<Entry>,
     #[serde(default, rename(deserialize = "pkg-config"))]
     pkg_config: Vec<Entry>,
+    #[serde(default)]
+    typelib: Vec<Entry>,
+    #[serde(default)]
+    gir: Vec<Entry>,
+    #[serde(default)]
+    apparmor: Vec<Entry>,
+    #[serde(default)]
+    selinux: Vec<Entry>,
+    #[serde(default)]
+    custom: Vec<CustomEntry>,
 }
 
 impl Package {
+    pub fn project_type(&self) -> Type {
+        self.project_type.clone().unwrap_or_default()
+    }
+
+    // Merge the entries of the same package defined in another file
+    pub fn merge(
+        &mut self,
+        other: Self,
+    ) -> Result<()> {
+        let name = self.name.as_deref().unwrap_or_default();
+        ensure!(
+            self.project_type() == other.project_type()
+                || self.project_type() == Type::Default
+                || other.project_type() == Type::Default,
+            "package {} has different types in its files",
+            name
+        );
+        if self.project_type() == Type::Default && other.project_type.is_some() {
+            self.project_type = other.project_type;
+        }
+        ensure!(
+            self.dirs.is_none() || other.dirs.is_none(),
+            "package {} defines dirs in more than one file",
+            name
+        );
+        if other.dirs.is_some() {
+            self.dirs = other.dirs;
+        }
+        ensure!(
+            self.version.is_none() || other.version.is_none() || self.version == other.version,
+            "package {} sets version to different values in its files",
+            name
+        );
+        if other.version.is_some() {
+            self.version = other.version;
+        }
+        ensure!(
+            self.templating.is_none()
+                || other.templating.is_none()
+                || self.templating == other.templating,
+            "package {} sets templating to different values in its files",
+            name
+        );
+        if other.templating.is_some() {
+            self.templating = other.templating;
+        }
+
+        macro_rules! append {
+            ( $($field:ident).+ ) => {
+                self.$($field).+.extend(other.$($field).+);
+            };
+        }
+        append!(exe);
+        append!(admin_exe);
+        append!(libs);
+        append!(libexec);
+        append!(includes);
+        append!(man);
+        append!(data);
+        append!(docs);
+        append!(config);
+        append!(user_config);
+        append!(desktop_files);
+        append!(autostart);
+        append!(environment);
+        append!(profile);
+        append!(appstream_metadata);
+        append!(kde_services);
+        append!(qt_plugins);
+        append!(completions.bash);
+        append!(completions.elvish);
+        append!(completions.fish);
+        append!(completions.zsh);
+        append!(completions.auto);
+        append!(shell_functions.bash);
+        append!(shell_functions.fish);
+        append!(shell_functions.zsh);
+        append!(pam_modules);
+        append!(systemd_units);
+        append!(systemd_user_units);
+        append!(systemd_preset);
+        append!(modules_load);
+        append!(binfmt);
+        append!(icons);
+        append!(fonts);
+        append!(kernel_modules);
+        append!(polkit_rules);
+        append!(mime);
+        append!(terminfo);
+        append!(licenses);
+        append!(pkg_config);
+        append!(typelib);
+        append!(gir);
+        append!(apparmor);
+        append!(selinux);
+        append!(custom);
+
+        Ok(())
+    }
+
+    // Remove the entries of the sections that should not be installed
+    fn filter_sections(
+        &mut self,
+        only: &[String],
+        skip: &[String],
+        section_dirs: &BTreeMap<String, Utf8PathBuf>,
+    ) -> Result<()> {
+        macro_rules! filter {
+            ( $( $name:literal => [ $( $($field:ident).+ ),+ ] ),* $(,)? ) => {
+                let sections = [$($name),*];
+                for section in only.iter().chain(skip) {
+                    ensure!(
+                        sections.contains(&section.as_str()),
+                        "unknown entry type '{}', the valid types are: {}",
+                        section,
+                        sections.join(", ")
+                    );
+                }
+                // The sections installed in a directory for each shell cannot be remapped
+                let remappable = sections
+                    .iter()
+                    .filter(|name| !["completions", "shell-functions", "custom"].contains(name))
+                    .copied()
+                    .collect::<Vec<_>>();
+                for section in section_dirs.keys() {
+                    ensure!(
+                        remappable.contains(&section.as_str()),
+                        "unknown entry type '{}' in section_dirs, the valid types are: {}",
+                        section,
+                        remappable.join(", ")
+                    );
+                }
+                $(
+                    let selected = only.is_empty() || only.iter().any(|section| section == $name);
+                    if !selected || skip.iter().any(|section| section == $name) {
+                        $( self.$($field).+ = Default::default(); )+
+                    }
+                )*
+            };
+        }
+
+        filter!(
+            "exe" => [exe],
+            "admin-exe" => [admin_exe],
+            "libs" => [libs],
+            "libexec" => [libexec],
+            "includes" => [includes],
+            "man" => [man],
+            "data" => [data],
+            "docs" => [docs],
+            "config" => [config],
+            "user-config" => [user_config],
+            "desktop-files" => [desktop_files],
+            "autostart" => [autostart],
+            "environment" => [environment],
+            "profile" => [profile],
+            "appstream-metadata" => [appstream_metadata],
+            "kde-services" => [kde_services],
+            "qt-plugins" => [qt_plugins],
+            "completions" => [completions.bash, completions.elvish, completions.fish, completions.zsh, completions.auto],
+            "shell-functions" => [shell_functions.bash, shell_functions.fish, shell_functions.zsh],
+            "pam-modules" => [pam_modules],
+            "systemd-units" => [systemd_units],
+            "systemd-user-units" => [systemd_user_units],
+            "systemd-preset" => [systemd_preset],
+            "modules-load" => [modules_load],
+            "binfmt" => [binfmt],
+            "icons" => [icons],
+            "fonts" => [fonts],
+            "kernel-modules" => [kernel_modules],
+            "polkit-rules" => [polkit_rules],
+            "mime" => [mime],
+            "terminfo" => [terminfo],
+            "licenses" => [licenses],
+            "pkg-config" => [pkg_config],
+            "typelib" => [typelib],
+            "gir" => [gir],
+            "apparmor" => [apparmor],
+            "selinux" => [selinux],
+            "custom" => [custom],
+        );
+
+        Ok(())
+    }
+
+    // Remove the entries whose when condition is not enabled
+    fn filter_features(
+        &mut self,
+        features: &[String],
+        rinstall_version: &Version,
+    ) -> Result<()> {
+        let requires = VersionReq::parse(">=0.3.0").unwrap();
+        let enabled = |when: &Option<String>| {
+            when.as_ref()
+                .is_none_or(|feature| features.contains(feature))
+        };
+        macro_rules! filter_features {
+            ( $variant:path => [ $( $($field:ident).+ ),+ ] ) => {
+                $(
+                    ensure!(
+                        self.$($field).+.iter().all(|$variant(entry)| entry.when.is_none())
+                            || requires.matches(rinstall_version),
+                        "when requires version {}",
+                        requires
+                    );
+                    self.$($field).+.retain(|$variant(entry)| enabled(&entry.when));
+                )+
+            };
+        }
+        filter_features!(Entry::InstallEntry => [
+            exe,
+            admin_exe,
+            libs,
+            libexec,
+            includes,
+            data,
+            docs,
+            config,
+            user_config,
+            desktop_files,
+            autostart,
+            environment,
+            profile,
+            appstream_metadata,
+            kde_services,
+            qt_plugins,
+            shell_functions.bash,
+            shell_functions.fish,
+            shell_functions.zsh,
+            pam_modules,
+            systemd_preset,
+            modules_load,
+            binfmt,
+            fonts,
+            kernel_modules,
+            polkit_rules,
+            mime,
+            terminfo,
+            licenses,
+            pkg_config,
+            typelib,
+            gir,
+            apparmor,
+            selinux
+        ]);
+        filter_features!(ManEntry::ManPage => [man]);
+        filter_features!(CompletionEntry::Completion => [
+            completions.bash,
+            completions.elvish,
+            completions.fish,
+            completions.zsh
+        ]);
+        filter_features!(SystemdUnitEntry::SystemdUnit => [systemd_units, systemd_user_units]);
+        filter_features!(IconEntry::Icon => [icons]);
+        // The custom entries are not `Entry`, filter them by feature here
+        self.custom.retain(|entry| enabled(&entry.when));
+
+        Ok(())
+    }
+
     // Generate a vector of InstallTarget from a package defined in install.yml
     pub fn targets(
-        self,
+        mut self,
         dirs: &Dirs,
+        project: &Project,
         rinstall_version: &Version,
-        system_install: bool,
+        options: &TargetOptions,
     ) -> Result<Vec<InstallTarget>> {
-        let allowed_version = vec!["0.1.0", "0.2.0"];
-        allowed_version
-            .iter()
-            .map(|v| Version::parse(v).unwrap())
-            .find(|v| v == rinstall_version)
-            .with_context(|| format!("{} is not a valid rinstall version", rinstall_version))?;
+        let TargetOptions {
+            system_install,
+            man_compression,
+            enable_units,
+            libexec_subdir,
+            update_mandb,
+            ..
+        } = *options;
+        let supported_versions = VersionReq::parse(SUPPORTED_VERSIONS).unwrap();
+        if !supported_versions.matches(rinstall_version) {
+            let current_version = Version::parse(env!("CARGO_PKG_VERSION")).unwrap();
+            ensure!(
+                *rinstall_version <= current_version,
+                "rinstall version {} is not supported, this rinstall build supports up to {}",
+                rinstall_version,
+                current_version
+            );
+            bail!(
+                "{} is not a valid rinstall version, the supported versions are {}",
+                rinstall_version,
+                supported_versions
+            );
+        }
 
         self.check_entries(rinstall_version)?;
+        self.filter_sections(&options.only, &options.skip, &dirs.section_dirs)?;
+        self.filter_features(&options.features, rinstall_version)?;
+        self.completions.route_auto_dirs(project)?;
+        self.apply_default_templating();
 
         let package_name = self.name.unwrap();
         let mut results = Vec::new();
 
-        fn get_files(
-            files: Vec<Entry>,
-            install_dir: &Utf8Path,
-            name: &str,
-            replace: FilesPolicy,
-        ) -> Result<Vec<InstallTarget>> {
+        let get_files = |files: Vec<Entry>,
+                         install_dir: &Utf8Path,
+                         name: &'static str,
+                         replace: FilesPolicy|
+         -> Result<Vec<InstallTarget>> {
+            let install_dir = dirs.section_dir(name, install_dir);
             files
                 .into_iter()
                 .map(|entry| -> Result<InstallTarget> {
                     let Entry::InstallEntry(entry) = entry;
-                    InstallTarget::new(entry, install_dir, replace)
+                    InstallTarget::new(entry, &install_dir, replace, name)
                 })
                 .collect::<Result<Vec<InstallTarget>>>()
                 .with_context(|| format!("error while iterating {} files", name))
-        }
+        };
 
         results.extend(get_files(
             self.exe,
@@ -151,9 +630,16 @@ impl Package {
             results.extend(get_files(
                 self.admin_exe,
                 sbindir,
-                "admin_exe",
+                "admin-exe",
                 FilesPolicy::Replace,
             )?);
+        } else {
+            // There is no sbindir for user installations
+            ensure!(
+                self.admin_exe.is_empty(),
+                "the admin-exe entries can only be installed system-wide, pass --system \
+                 or skip them with --skip admin-exe"
+            );
         }
         results.extend(get_files(
             self.libs,
@@ -161,9 +647,14 @@ impl Package {
             "libs",
             FilesPolicy::Replace,
         )?);
+        let libexecdir = if libexec_subdir {
+            dirs.libexecdir.join(&package_name)
+        } else {
+            dirs.libexecdir.clone()
+        };
         results.extend(get_files(
             self.libexec,
-            &dirs.libexecdir,
+            &libexecdir,
             "libexec",
             FilesPolicy::Replace,
         )?);
@@ -189,34 +680,43 @@ impl Package {
         )?);
 
         if let Some(mandir) = &dirs.mandir {
+            // The section subdirectories, e.g. man1, are still appended
+            let mandir = &dirs.section_dir("man", mandir);
             results.extend(
                 self.man
                     .into_iter()
                     .map(|entry| -> Result<InstallTarget> {
-                        let Entry::InstallEntry(entry) = entry;
-                        ensure!(
-                            !entry.source.as_str().ends_with('/'),
-                            "the man entry cannot be a directory"
-                        );
-                        let use_source_name = entry
-                            .destination
-                            .as_ref()
-                            .map_or(true, |destination| destination.as_str().ends_with('/'));
-                        let name = if use_source_name {
-                            &entry.source
-                        } else {
-                            entry.destination.as_ref().unwrap()
+                        let ManEntry::ManPage(man_page) = entry;
+                        let install_dir = man_page.install_dir(mandir)?;
+                        let aliases = man_page.alias_names(&install_dir)?;
+                        // Do not compress the man pages twice, the ones compressed with
+                        // a different algorithm are decompressed first
+                        let source_compression = man_page.compression();
+                        let (compression, decompress) = match (man_compression, source_compression)
+                        {
+                            (None, _) => (ManCompression::None, false),
+                            (Some(compression), None) => (compression, false),
+                            (Some(compression), Some(source_compression)) => {
+                                if compression.extension() == Some(source_compression) {
+                                    (ManCompression::None, false)
+                                } else {
+                                    (compression, true)
+                                }
+                            }
                         };
-                        let man_cat = name
-                            .extension()
-                            .with_context(|| format!("unable to get extension of file {:?}", name))?
-                            .to_string();
-                        ensure!(
-                            man_cat.chars().next().unwrap().is_ascii_digit(),
-                            "the last character should be a digit from 1 to 8"
-                        );
-                        let install_dir = mandir.join(format!("man{}", &man_cat));
-                        InstallTarget::new(entry, &install_dir, FilesPolicy::Replace)
+                        let mut target = InstallTarget::new(
+                            man_page.into(),
+                            &install_dir,
+                            FilesPolicy::Replace,
+                            "man",
+                        )?;
+                        target.compression = compression;
+                        target.decompress = decompress;
+                        target.aliases = aliases;
+                        if update_mandb {
+                            target.post_install = Some(PostInstall::ManDatabase(mandir.clone()));
+                        }
+                        Ok(target)
                     })
                     .collect::<Result<Vec<InstallTarget>>>()
                     .context("error while iterating man pages")?,
@@ -250,13 +750,104 @@ impl Package {
             )?);
         }
 
-        results.extend(get_files(
+        let applications_dir =
+            dirs.section_dir("desktop-files", &dirs.datarootdir.join("applications"));
+        let mut desktop_files = get_files(
             self.desktop_files,
-            &dirs.datarootdir.join("applications"),
+            &applications_dir,
             "desktop-files",
             FilesPolicy::Replace,
+        )?;
+        if system_install {
+            for target in &mut desktop_files {
+                target.post_install = Some(PostInstall::DesktopDatabase(applications_dir.clone()));
+            }
+        }
+        results.extend(desktop_files);
+
+        for Entry::InstallEntry(entry) in &self.autostart {
+            let name = entry.installed_path();
+            ensure!(
+                name.as_str().ends_with('/') || name.extension() == Some("desktop"),
+                "autostart entry {:?} is not a .desktop file",
+                name
+            );
+        }
+        let autostart_dir = if system_install {
+            dirs.sysconfdir.join("xdg/autostart")
+        } else {
+            dirs.sysconfdir.join("autostart")
+        };
+        results.extend(get_files(
+            self.autostart,
+            &autostart_dir,
+            "autostart",
+            FilesPolicy::Replace,
+        )?);
+
+        for Entry::InstallEntry(entry) in &self.environment {
+            let name = entry.installed_path();
+            ensure!(
+                name.as_str().ends_with('/') || name.extension() == Some("conf"),
+                "environment entry {:?} is not a .conf file",
+                name
+            );
+        }
+        // systemd only reads the snippets in lib, not in datarootdir
+        let environment_dir = if system_install {
+            dirs.prefix
+                .as_ref()
+                .context("prefix is required to install the environment entries")?
+                .join("lib/environment.d")
+        } else {
+            dirs.sysconfdir.join("environment.d")
+        };
+        results.extend(get_files(
+            self.environment,
+            &environment_dir,
+            "environment",
+            FilesPolicy::Replace,
+        )?);
+
+        for Entry::InstallEntry(entry) in &self.profile {
+            let name = entry.installed_path();
+            ensure!(
+                name.as_str().ends_with('/') || name.extension() == Some("sh"),
+                "profile entry {:?} is not a .sh file",
+                name
+            );
+        }
+        // There is no profile.d directory sourced by the shells for a single user
+        if system_install {
+            results.extend(get_files(
+                self.profile,
+                &dirs.sysconfdir.join("profile.d"),
+                "profile",
+                FilesPolicy::Replace,
+            )?);
+        } else if !self.profile.is_empty() {
+            warn!(
+                "the {} entries can only be installed system-wide, skipping them",
+                "profile".bright_black().italic()
+            );
+        }
+
+        results.extend(get_files(
+            self.kde_services,
+            &dirs.kde_servicesdir,
+            "kde-services",
+            FilesPolicy::Replace,
         )?);
 
+        if let Some(qt_pluginsdir) = &dirs.qt_pluginsdir {
+            results.extend(get_files(
+                self.qt_plugins,
+                qt_pluginsdir,
+                "qt-plugins",
+                FilesPolicy::Replace,
+            )?);
+        }
+
         if system_install {
             results.extend(get_files(
                 self.appstream_metadata,
@@ -266,57 +857,96 @@ impl Package {
             )?);
         }
 
-        let mut completions = self
-            .completions
-            .bash
-            .into_iter()
-            .map(|completion| {
-                (
-                    completion,
-                    if system_install {
-                        "bash-completion/completions"
-                    } else {
-                        "bash-completion"
-                    },
-                )
-            })
-            .collect::<Vec<(Entry, &'static str)>>();
-        completions.extend(
-            self.completions
-                .elvish
-                .into_iter()
-                .map(|completion| (completion, "elvish/lib")),
-        );
-        if system_install {
-            completions.extend(
-                self.completions
-                    .fish
-                    .into_iter()
-                    .map(|completion| (completion, "fish/vendor_completions.d")),
-            );
-            completions.extend(
-                self.completions
-                    .zsh
-                    .into_iter()
-                    .map(|completion| (completion, "zsh/site-functions")),
-            );
+        // The name of the completion file of a command, as looked up by each shell
+        let completions = [
+            (self.completions.bash, &dirs.bash_completionsdir, ("", "")),
+            (
+                self.completions.elvish,
+                &dirs.elvish_completionsdir,
+                ("", ".elv"),
+            ),
+            (
+                self.completions.fish,
+                &dirs.fish_completionsdir,
+                ("", ".fish"),
+            ),
+            (self.completions.zsh, &dirs.zsh_completionsdir, ("_", "")),
+        ];
+        for (entries, completionsdir, (prefix, suffix)) in completions {
+            // fish and zsh completions are only installed system-wide
+            if let Some(completionsdir) = completionsdir {
+                results.extend(
+                    entries
+                        .into_iter()
+                        .map(
+                            |CompletionEntry::Completion(mut completion)| -> Result<InstallTarget> {
+                                ensure!(
+                                    completion.args.is_none()
+                                        || completion
+                                            .templating
+                                            .is_none_or(|mode| mode == TemplatingMode::Disabled),
+                                    "the output of {:?} cannot be templated",
+                                    completion.source
+                                );
+                                if let Some(command) = &completion.command {
+                                    ensure!(
+                                        completion.destination.is_none(),
+                                        "the completion {:?} cannot set both dst and command",
+                                        completion.source
+                                    );
+                                    completion.destination = Some(Utf8PathBuf::from(format!(
+                                        "{}{}{}",
+                                        prefix, command, suffix
+                                    )));
+                                }
+                                let args = completion.args.clone();
+                                let mut target = InstallTarget::new(
+                                    completion.into(),
+                                    completionsdir,
+                                    FilesPolicy::Replace,
+                                    "completions",
+                                )?;
+                                target.generate_args = args;
+                                Ok(target)
+                            },
+                        )
+                        .collect::<Result<Vec<InstallTarget>>>()
+                        .context("error while iterating completions files")?,
+                );
+            }
+        }
+
+        // zsh and fish autoload the functions from their directories, while
+        // bash has no such directory and the libraries are sourced by their path
+        let shell_functions = [
+            (
+                self.shell_functions.bash,
+                dirs.datarootdir.join("bash/functions"),
+            ),
+            (
+                self.shell_functions.fish,
+                if system_install {
+                    dirs.datarootdir.join("fish/vendor_functions.d")
+                } else {
+                    dirs.sysconfdir.join("fish/functions")
+                },
+            ),
+            (
+                self.shell_functions.zsh,
+                dirs.datarootdir.join("zsh/site-functions"),
+            ),
+        ];
+        for (entries, functions_dir) in shell_functions {
+            results.extend(get_files(
+                entries,
+                &functions_dir,
+                "shell-functions",
+                FilesPolicy::Replace,
+            )?);
         }
-        results.extend(
-            completions
-                .into_iter()
-                .map(|(entry, completionsdir)| -> Result<InstallTarget> {
-                    let Entry::InstallEntry(entry) = entry;
-                    InstallTarget::new(
-                        entry,
-                        &dirs.datarootdir.join(completionsdir),
-                        FilesPolicy::Replace,
-                    )
-                })
-                .collect::<Result<Vec<InstallTarget>>>()
-                .context("error while iterating completion files")?,
-        );
 
         if let Some(pam_modulesdir) = &dirs.pam_modulesdir {
+            let pam_modulesdir = &dirs.section_dir("pam-modules", pam_modulesdir);
             results.extend(
                 self.pam_modules
                     .into_iter()
@@ -325,6 +955,9 @@ impl Package {
                             source,
                             destination,
                             templating,
+                            rename,
+                            when,
+                            vars,
                         }) = entry;
 
                         let destination = if destination.is_some() {
@@ -343,9 +976,13 @@ impl Package {
                                 source,
                                 destination,
                                 templating,
+                                rename,
+                                when,
+                                vars,
                             },
                             pam_modulesdir,
                             FilesPolicy::Replace,
+                            "pam-modules",
                         )
                     })
                     .collect::<Result<Vec<InstallTarget>>>()
@@ -353,21 +990,120 @@ impl Package {
             );
         }
 
+        let mut units = Vec::new();
         if system_install {
-            results.extend(get_files(
+            units.push((
                 self.systemd_units,
-                &dirs.systemd_unitsdir.join("system"),
+                dirs.section_dir("systemd-units", &dirs.systemd_unitsdir.join("system")),
                 "systemd-units",
-                FilesPolicy::Replace,
-            )?);
+                UnitScope::System,
+            ));
         }
-        results.extend(get_files(
+        units.push((
             self.systemd_user_units,
-            &dirs.systemd_unitsdir.join("user"),
+            dirs.section_dir("systemd-user-units", &dirs.systemd_unitsdir.join("user")),
             "systemd-user-units",
-            FilesPolicy::Replace,
-        )?);
+            if system_install {
+                UnitScope::Global
+            } else {
+                UnitScope::User
+            },
+        ));
+        for (entries, unitsdir, name, scope) in units {
+            results.extend(
+                entries
+                    .into_iter()
+                    .map(
+                        |SystemdUnitEntry::SystemdUnit(unit)| -> Result<InstallTarget> {
+                            let unit_name = unit.name().map(str::to_string);
+                            if unit_name.is_none() {
+                                ensure!(
+                                    !unit.enable,
+                                    "unable to enable the units in directory {:?}",
+                                    unit.source
+                                );
+                                if enable_units {
+                                    warn!(
+                                        "the units in directory {} will not be enabled",
+                                        unit.source.as_str().yellow().bold()
+                                    );
+                                }
+                            }
+                            let enable = unit.enable || enable_units;
+                            let mut target = InstallTarget::new(
+                                unit.into(),
+                                &unitsdir,
+                                FilesPolicy::Replace,
+                                name,
+                            )?;
+                            if enable {
+                                target.post_install = unit_name.map(|name| {
+                                    PostInstall::EnableUnit(EnabledUnit { name, scope })
+                                });
+                            }
+                            Ok(target)
+                        },
+                    )
+                    .collect::<Result<Vec<InstallTarget>>>()
+                    .with_context(|| format!("error while iterating {} files", name))?,
+            );
+        }
 
+        // The other systemd directories are next to the units one, in lib
+        let systemd_libdir = dirs
+            .systemd_unitsdir
+            .parent()
+            .unwrap_or(&dirs.systemd_unitsdir);
+        let systemd_dirs = [
+            (
+                self.systemd_preset,
+                dirs.systemd_unitsdir.join("system-preset"),
+                "systemd-preset",
+                "preset",
+            ),
+            (
+                self.modules_load,
+                systemd_libdir.join("modules-load.d"),
+                "modules-load",
+                "conf",
+            ),
+            (
+                self.binfmt,
+                systemd_libdir.join("binfmt.d"),
+                "binfmt",
+                "conf",
+            ),
+        ];
+        for (entries, dir, name, extension) in systemd_dirs {
+            for Entry::InstallEntry(entry) in &entries {
+                let installed_path = entry.installed_path();
+                ensure!(
+                    installed_path.as_str().ends_with('/')
+                        || installed_path.extension() == Some(extension),
+                    "{} entry {:?} is not a .{} file",
+                    name,
+                    installed_path,
+                    extension
+                );
+            }
+            if system_install {
+                results.extend(get_files(
+                    entries,
+                    &dirs.section_dir(name, &dir),
+                    name,
+                    FilesPolicy::Replace,
+                )?);
+            } else if !entries.is_empty() {
+                warn!(
+                    "the {} entries can only be installed system-wide, skipping them",
+                    name.bright_black().italic()
+                );
+            }
+        }
+
+        // The icons destination starts with icons/ or pixmaps/, so the remapped
+        // directory replaces datarootdir
+        let icons_dir = dirs.section_dir("icons", &dirs.datarootdir);
         results.extend(
             self.icons
                 .into_iter()
@@ -376,9 +1112,8 @@ impl Package {
                         IconEntry::Icon(icon) => icon,
                     }
                 })
-                .filter(|icon| system_install || !icon.pixmaps)
                 .map(|icon| -> Result<InstallTarget> {
-                    InstallTarget::new(
+                    let mut target = InstallTarget::new(
                         InstallEntry {
                             source: icon.source.clone(),
                             destination: Some(icon.get_destination().with_context(|| {
@@ -387,17 +1122,140 @@ impl Package {
                                     icon.source.clone()
                                 )
                             })?),
-                            templating: false,
+                            templating: None,
+                            rename: BTreeMap::new(),
+                            when: None,
+                            vars: BTreeMap::new(),
                         },
-                        &dirs.datarootdir,
+                        &icons_dir,
                         FilesPolicy::Replace,
-                    )
+                        "icons",
+                    )?;
+                    if system_install {
+                        target.post_install = icon
+                            .theme_dir()
+                            .map(|theme_dir| PostInstall::IconCache(icons_dir.join(theme_dir)));
+                    }
+                    Ok(target)
                 })
                 .collect::<Result<Vec<InstallTarget>>>()
                 .context("error while iterating icons")?,
         );
 
+        let fonts_dir = if system_install {
+            dirs.datarootdir.join("fonts").join(&package_name)
+        } else {
+            dirs.datarootdir.join("fonts")
+        };
+        let fonts_dir = dirs.section_dir("fonts", &fonts_dir);
+        for Entry::InstallEntry(entry) in &self.fonts {
+            let name = entry.installed_path();
+            let is_font = name.as_str().ends_with('/')
+                || name.extension().is_some_and(|extension| {
+                    FONT_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                });
+            if !is_font {
+                warn!(
+                    "font {} does not have any of the {} extensions",
+                    name.as_str().yellow().bold(),
+                    FONT_EXTENSIONS.join(", ").bright_black()
+                );
+            }
+        }
+        let mut fonts = get_files(self.fonts, &fonts_dir, "fonts", FilesPolicy::Replace)?;
+        for target in &mut fonts {
+            target.post_install = Some(PostInstall::FontCache(fonts_dir.clone()));
+        }
+        results.extend(fonts);
+
+        for Entry::InstallEntry(entry) in &self.kernel_modules {
+            let name = entry.installed_path();
+            ensure!(
+                name.as_str().ends_with('/')
+                    || KERNEL_MODULE_EXTENSIONS
+                        .iter()
+                        .any(|extension| name.as_str().ends_with(&format!(".{}", extension))),
+                "kernel-modules entry {:?} does not have any of the {} extensions",
+                name,
+                KERNEL_MODULE_EXTENSIONS.join(", ")
+            );
+        }
+        // The modules are only loaded from the system directory
+        if system_install && !self.kernel_modules.is_empty() {
+            let kernel_version = match &options.kernel_version {
+                Some(kernel_version) => kernel_version.clone(),
+                None => running_kernel_version()?,
+            };
+            let modules_dir = Utf8Path::new(KERNEL_MODULES_DIR).join(&kernel_version);
+            let mut kernel_modules = get_files(
+                self.kernel_modules,
+                &modules_dir.join("extra"),
+                "kernel-modules",
+                FilesPolicy::Replace,
+            )?;
+            for target in &mut kernel_modules {
+                target.post_install = Some(PostInstall::Depmod(kernel_version.clone()));
+            }
+            results.extend(kernel_modules);
+        } else if !self.kernel_modules.is_empty() {
+            warn!(
+                "the {} entries can only be installed system-wide, skipping them",
+                "kernel-modules".bright_black().italic()
+            );
+        }
+
+        for Entry::InstallEntry(entry) in &self.polkit_rules {
+            let name = entry.installed_path();
+            ensure!(
+                name.as_str().ends_with('/') || name.extension() == Some("rules"),
+                "polkit-rules entry {:?} does not have the rules extension",
+                name
+            );
+        }
+        // polkitd only reads the rules from the system directories
+        if system_install {
+            let polkit_rules_dir =
+                dirs.section_dir("polkit-rules", &dirs.datarootdir.join("polkit-1/rules.d"));
+            let mut polkit_rules = get_files(
+                self.polkit_rules,
+                &polkit_rules_dir,
+                "polkit-rules",
+                FilesPolicy::Replace,
+            )?;
+            // polkitd cannot read the rules with a restrictive --file-mode
+            for target in &mut polkit_rules {
+                target.mode = Some(0o644);
+                target.owner.clone_from(&options.polkit_owner);
+            }
+            results.extend(polkit_rules);
+        } else if !self.polkit_rules.is_empty() {
+            warn!(
+                "the {} entries can only be installed system-wide, skipping them",
+                "polkit-rules".bright_black().italic()
+            );
+        }
+
+        for Entry::InstallEntry(entry) in &self.mime {
+            let name = entry.installed_path();
+            ensure!(
+                name.as_str().ends_with('/') || name.extension() == Some("xml"),
+                "mime entry {:?} is not a shared-mime-info xml file",
+                name
+            );
+        }
+        let mime_packages_dir = dirs.section_dir("mime", &dirs.datarootdir.join("mime/packages"));
+        let mime_dir = mime_packages_dir
+            .parent()
+            .unwrap_or(&mime_packages_dir)
+            .to_path_buf();
+        let mut mime = get_files(self.mime, &mime_packages_dir, "mime", FilesPolicy::Replace)?;
+        for target in &mut mime {
+            target.post_install = Some(PostInstall::MimeDatabase(mime_dir.clone()));
+        }
+        results.extend(mime);
+
         if system_install {
+            let terminfo_dir = dirs.section_dir("terminfo", &dirs.datarootdir.join("terminfo"));
             results.extend(
                 self.terminfo
                     .into_iter()
@@ -410,7 +1268,7 @@ impl Package {
                         let use_source_name = entry
                             .destination
                             .as_ref()
-                            .map_or(true, |destination| destination.as_str().ends_with('/'));
+                            .is_none_or(|destination| destination.as_str().ends_with('/'));
                         let name = if use_source_name {
                             &entry.source
                         } else {
@@ -426,8 +1284,8 @@ impl Package {
                             })?
                             .to_lowercase()
                             .to_string();
-                        let install_dir = dirs.datarootdir.join("terminfo").join(&initial);
-                        InstallTarget::new(entry, &install_dir, FilesPolicy::Replace)
+                        let install_dir = terminfo_dir.join(&initial);
+                        InstallTarget::new(entry, &install_dir, FilesPolicy::Replace, "terminfo")
                     })
                     .collect::<Result<Vec<InstallTarget>>>()
                     .context("error while iterating terminfo files")?,
@@ -441,18 +1299,263 @@ impl Package {
             FilesPolicy::Replace,
         )?);
 
-        if system_install {
-            results.extend(get_files(
-                self.pkg_config,
-                &dirs.libdir.join("pkgconfig"),
-                "pkg-config",
+        let pkg_config_dir = if system_install {
+            dirs.libdir.join("pkgconfig")
+        } else {
+            dirs.datarootdir.join("pkgconfig")
+        };
+        // Templating is enabled by default so that the directories in the
+        // pkg-config files match the installation. Keep the previous behaviour
+        // for older versions of install.yml
+        let pkg_config_templating = if VersionReq::parse(">=0.3.0")
+            .unwrap()
+            .matches(rinstall_version)
+        {
+            TemplatingMode::Replace
+        } else {
+            TemplatingMode::Disabled
+        };
+        let pkg_config = self
+            .pkg_config
+            .into_iter()
+            .map(|Entry::InstallEntry(mut entry)| {
+                entry.templating.get_or_insert(pkg_config_templating);
+                Entry::InstallEntry(entry)
+            })
+            .collect();
+        results.extend(get_files(
+            pkg_config,
+            &pkg_config_dir,
+            "pkg-config",
+            FilesPolicy::Replace,
+        )?);
+
+        // The GObject-Introspection data is only searched in the system directories
+        let introspection = [
+            (
+                self.typelib,
+                dirs.libdir.join("girepository-1.0"),
+                "typelib",
+            ),
+            (self.gir, dirs.datarootdir.join("gir-1.0"), "gir"),
+        ];
+        for (entries, dir, name) in introspection {
+            for Entry::InstallEntry(entry) in &entries {
+                let installed_path = entry.installed_path();
+                ensure!(
+                    installed_path.as_str().ends_with('/')
+                        || installed_path.extension() == Some(name),
+                    "{} entry {:?} is not a .{} file",
+                    name,
+                    installed_path,
+                    name
+                );
+            }
+            if system_install {
+                results.extend(get_files(
+                    entries,
+                    &dirs.section_dir(name, &dir),
+                    name,
+                    FilesPolicy::Replace,
+                )?);
+            } else if !entries.is_empty() {
+                warn!(
+                    "the {} entries can only be installed system-wide, skipping them",
+                    name.bright_black().italic()
+                );
+            }
+        }
+
+        for Entry::InstallEntry(entry) in &self.selinux {
+            let installed_path = entry.installed_path();
+            ensure!(
+                installed_path.extension() == Some("pp"),
+                "selinux entry {:?} is not a .pp policy module",
+                installed_path
+            );
+        }
+        // The security policies are only read from the system directories. The AppArmor
+        // profiles are edited by the administrators like the configs
+        let policies = [
+            (
+                self.apparmor,
+                dirs.sysconfdir.join("apparmor.d"),
+                "apparmor",
+                FilesPolicy::NoReplace,
+                PolicyKind::AppArmor,
+                options.load_apparmor,
+            ),
+            (
+                self.selinux,
+                dirs.datarootdir.join("selinux/packages"),
+                "selinux",
                 FilesPolicy::Replace,
+                PolicyKind::SELinux,
+                options.load_selinux,
+            ),
+        ];
+        for (entries, dir, name, files_policy, kind, load) in policies {
+            if !system_install {
+                if !entries.is_empty() {
+                    warn!(
+                        "the {} entries can only be installed system-wide, skipping them",
+                        name.bright_black().italic()
+                    );
+                }
+                continue;
+            }
+            let mut policies = get_files(entries, &dir, name, files_policy)?;
+            if load {
+                // The path is replaced by each installed file, see InstallTarget::post_install_hooks
+                for target in &mut policies {
+                    target.post_install = Some(PostInstall::LoadPolicy(LoadedPolicy {
+                        kind,
+                        path: target.destination.clone(),
+                    }));
+                }
+            }
+            results.extend(policies);
+        }
+
+        // The custom entries are installed as they are, without the conveniences
+        // of the other entry types
+        for entry in self.custom {
+            let install_dir = entry.install_dir(dirs)?;
+            results.push(InstallTarget::new(
+                entry.into(),
+                &install_dir,
+                FilesPolicy::Replace,
+                "custom",
             )?);
         }
 
+        let requires = VersionReq::parse(">=0.3.0").unwrap();
+        ensure!(
+            results
+                .iter()
+                .all(|target| target.templating != TemplatingMode::Handlebars)
+                || requires.matches(rinstall_version),
+            "handlebars templating requires version {}",
+            requires
+        );
+        ensure!(
+            results.iter().all(|target| target.vars.is_empty())
+                || requires.matches(rinstall_version),
+            "vars requires version {}",
+            requires
+        );
+
         Ok(results)
     }
 
+    // Set tmpl for the entries that do not set it, icons, fonts, kernel modules
+    // typelibs and SELinux modules are never templated
+    fn apply_default_templating(&mut self) {
+        let Some(templating) = self.templating else {
+            return;
+        };
+        macro_rules! default_templating {
+            ( $($($field:ident).+),* ) => {
+                $(
+                    for Entry::InstallEntry(entry) in &mut self.$($field).+ {
+                        entry.templating.get_or_insert(templating);
+                    }
+                )*
+            };
+        }
+        default_templating!(
+            exe,
+            admin_exe,
+            libs,
+            libexec,
+            includes,
+            data,
+            docs,
+            config,
+            user_config,
+            desktop_files,
+            autostart,
+            environment,
+            profile,
+            appstream_metadata,
+            kde_services,
+            qt_plugins,
+            shell_functions.bash,
+            shell_functions.fish,
+            shell_functions.zsh,
+            pam_modules,
+            systemd_preset,
+            modules_load,
+            binfmt,
+            polkit_rules,
+            mime,
+            terminfo,
+            licenses,
+            pkg_config,
+            gir,
+            apparmor
+        );
+        for ManEntry::ManPage(man_page) in &mut self.man {
+            man_page.templating.get_or_insert(templating);
+        }
+        // The output of the commands is never templated
+        for CompletionEntry::Completion(completion) in self
+            .completions
+            .bash
+            .iter_mut()
+            .chain(&mut self.completions.elvish)
+            .chain(&mut self.completions.fish)
+            .chain(&mut self.completions.zsh)
+            .filter(|CompletionEntry::Completion(completion)| completion.args.is_none())
+        {
+            completion.templating.get_or_insert(templating);
+        }
+        for SystemdUnitEntry::SystemdUnit(unit) in self
+            .systemd_units
+            .iter_mut()
+            .chain(&mut self.systemd_user_units)
+        {
+            unit.templating.get_or_insert(templating);
+        }
+        for entry in &mut self.custom {
+            entry.templating.get_or_insert(templating);
+        }
+    }
+
+    // The directories created only for this package, e.g. datadir/<pkg-name>;
+    // the sections remapped in section_dirs do not own their directory
+    pub fn package_dirs(
+        &self,
+        dirs: &Dirs,
+        options: &TargetOptions,
+    ) -> Vec<Utf8PathBuf> {
+        let system_install = options.system_install;
+        let package_name = self.name.as_ref().unwrap();
+        let owned = |section: &str| !dirs.section_dirs.contains_key(section);
+        let mut results = Vec::new();
+        if options.libexec_subdir && !self.libexec.is_empty() && owned("libexec") {
+            results.push(dirs.libexecdir.join(package_name));
+        }
+        if !self.data.is_empty() && owned("data") {
+            results.push(dirs.datadir.join(package_name));
+        }
+        if let (true, Some(docdir)) = (system_install, &dirs.docdir) {
+            if (!self.docs.is_empty() && owned("docs"))
+                || (!self.user_config.is_empty() && owned("user-config"))
+            {
+                results.push(docdir.join(package_name));
+            }
+        }
+        if !self.licenses.is_empty() && owned("licenses") {
+            results.push(dirs.datarootdir.join("licenses").join(package_name));
+        }
+        if system_install && !self.fonts.is_empty() && owned("fonts") {
+            results.push(dirs.datarootdir.join("fonts").join(package_name));
+        }
+
+        results
+    }
+
     fn check_entries(
         &self,
         rinstall_version: &Version,
@@ -474,7 +1577,7 @@ impl Package {
             };
         }
 
-        if self.project_type == Type::Custom
+        if self.project_type() == Type::Custom
             && VersionReq::parse(">=0.2.0")
                 .unwrap()
                 .matches(rinstall_version)
@@ -485,6 +1588,129 @@ impl Package {
                 "default".bright_black(),
             );
         }
+        let requires = VersionReq::parse(">=0.3.0").unwrap();
+        ensure!(
+            self.dirs.is_none() || requires.matches(rinstall_version),
+            "dirs requires version {}",
+            requires
+        );
+        ensure!(
+            self.templating.is_none() || requires.matches(rinstall_version),
+            "templating requires version {}",
+            requires
+        );
+        ensure!(
+            self.version.is_none() || requires.matches(rinstall_version),
+            "version requires version {}",
+            requires
+        );
+        ensure!(
+            self.man
+                .iter()
+                .all(|ManEntry::ManPage(man_page)| man_page.locale.is_none())
+                || requires.matches(rinstall_version),
+            "man:locale requires version {}",
+            requires
+        );
+        ensure!(
+            self.man
+                .iter()
+                .all(|ManEntry::ManPage(man_page)| man_page.section.is_none())
+                || requires.matches(rinstall_version),
+            "man:section requires version {}",
+            requires
+        );
+        ensure!(
+            self.man
+                .iter()
+                .all(|ManEntry::ManPage(man_page)| man_page.aliases.is_empty())
+                || requires.matches(rinstall_version),
+            "man:aliases requires version {}",
+            requires
+        );
+        ensure!(
+            self.systemd_units
+                .iter()
+                .chain(&self.systemd_user_units)
+                .all(|SystemdUnitEntry::SystemdUnit(unit)| !unit.enable)
+                || requires.matches(rinstall_version),
+            "systemd-units:enable requires version {}",
+            requires
+        );
+        macro_rules! check_rename {
+            ( $($($field:ident).+),* ) => {
+                $(
+                    ensure!(
+                        self.$($field).+
+                            .iter()
+                            .all(|Entry::InstallEntry(entry)| entry.rename.is_empty())
+                            || requires.matches(rinstall_version),
+                        "rename requires version {}",
+                        requires
+                    );
+                )*
+            };
+        }
+        check_rename!(
+            exe,
+            admin_exe,
+            libs,
+            libexec,
+            includes,
+            data,
+            docs,
+            config,
+            user_config,
+            desktop_files,
+            autostart,
+            environment,
+            profile,
+            appstream_metadata,
+            kde_services,
+            qt_plugins,
+            shell_functions.bash,
+            shell_functions.fish,
+            shell_functions.zsh,
+            pam_modules,
+            systemd_preset,
+            modules_load,
+            binfmt,
+            fonts,
+            kernel_modules,
+            polkit_rules,
+            mime,
+            terminfo,
+            licenses,
+            pkg_config,
+            typelib,
+            gir,
+            apparmor,
+            selinux
+        );
+        ensure!(
+            self.completions
+                .iter()
+                .all(|completion| completion.rename.is_empty())
+                || requires.matches(rinstall_version),
+            "rename requires version {}",
+            requires
+        );
+        ensure!(
+            self.completions
+                .iter()
+                .all(|completion| completion.args.is_none())
+                || requires.matches(rinstall_version),
+            "completions:args requires version {}",
+            requires
+        );
+        ensure!(
+            self.completions
+                .iter()
+                .all(|completion| completion.command.is_none())
+                || requires.matches(rinstall_version),
+            "completions:command requires version {}",
+            requires
+        );
         check_version!("exe", exe, ">=0.1.0");
         check_version!("admin_exe", admin_exe, ">=0.1.0");
         check_version!("libs", libs, ">=0.1.0");
@@ -496,19 +1722,221 @@ impl Package {
         check_version!("config", config, ">=0.1.0");
         check_version!("user-config", user_config, ">=0.1.0");
         check_version!("desktop-files", desktop_files, ">=0.1.0");
+        check_version!("autostart", autostart, ">=0.3.0");
+        check_version!("environment", environment, ">=0.3.0");
+        check_version!("profile", profile, ">=0.3.0");
         check_version!("appstream-metadata", appstream_metadata, ">=0.1.0");
+        check_version!("kde-services", kde_services, ">=0.3.0");
+        check_version!("qt-plugins", qt_plugins, ">=0.3.0");
         check_version_expr!("completions:bash", self.completions.bash, ">=0.1.0");
         check_version_expr!("completions:elvish", self.completions.elvish, ">=0.2.0");
         check_version_expr!("completions:fish", self.completions.fish, ">=0.1.0");
         check_version_expr!("completions:zsh", self.completions.zsh, ">=0.1.0");
+        check_version_expr!("completions:auto", self.completions.auto, ">=0.3.0");
+        check_version_expr!("shell-functions:bash", self.shell_functions.bash, ">=0.3.0");
+        check_version_expr!("shell-functions:fish", self.shell_functions.fish, ">=0.3.0");
+        check_version_expr!("shell-functions:zsh", self.shell_functions.zsh, ">=0.3.0");
         check_version!("pam-modules", pam_modules, ">=0.1.0");
         check_version!("systemd-units", systemd_units, ">=0.1.0");
         check_version!("systemd-user-units", systemd_user_units, ">=0.2.0");
+        check_version!("systemd-preset", systemd_preset, ">=0.3.0");
+        check_version!("modules-load", modules_load, ">=0.3.0");
+        check_version!("binfmt", binfmt, ">=0.3.0");
         check_version!("icons", icons, ">=0.1.0");
+        check_version!("fonts", fonts, ">=0.3.0");
+        check_version!("kernel-modules", kernel_modules, ">=0.3.0");
+        check_version!("polkit-rules", polkit_rules, ">=0.3.0");
+        check_version!("mime", mime, ">=0.3.0");
         check_version!("terminfo", terminfo, ">=0.1.0");
         check_version!("licenses", licenses, ">=0.1.0");
         check_version!("pkg-config", pkg_config, ">=0.1.0");
+        check_version!("typelib", typelib, ">=0.3.0");
+        check_version!("gir", gir, ">=0.3.0");
+        check_version!("apparmor", apparmor, ">=0.3.0");
+        check_version!("selinux", selinux, ">=0.3.0");
+        check_version!("custom", custom, ">=0.3.0");
 
         Ok(())
     }
 }
+
+// The version of the running kernel, used for the modules directory when
+// --kernel-version is not given
+fn running_kernel_version() -> Result<String> {
+    let output = Command::new("uname")
+        .arg("-r")
+        .output()
+        .context("unable to run uname -r")?;
+    let kernel_version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    ensure!(
+        output.status.success() && !kernel_version.is_empty(),
+        "unable to detect the kernel version, pass it with --kernel-version"
+    );
+
+    Ok(kernel_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(yaml: &str) -> Package {
+        let mut package: Package = serde_yaml::from_str(yaml).unwrap();
+        package.name = Some("foo".to_string());
+        package
+    }
+
+    fn system_dirs() -> Dirs {
+        let mut dirs_config = DirsConfig::system_config();
+        dirs_config.replace_placeholders(true).unwrap();
+        Dirs::new(dirs_config, true).unwrap()
+    }
+
+    // The user directories with the XDG placeholders already replaced
+    fn user_dirs() -> Dirs {
+        let dir = |dir: &str| Some(dir.to_string());
+        let dirs_config = DirsConfig {
+            prefix: dir("/home/user/.local"),
+            exec_prefix: dir("/home/user/.local"),
+            bindir: dir("/home/user/.local/bin"),
+            libdir: dir("/home/user/.local/lib"),
+            libexecdir: dir("/home/user/.local/libexec"),
+            datarootdir: dir("/home/user/.local/share"),
+            datadir: dir("/home/user/.local/share"),
+            sysconfdir: dir("/home/user/.config"),
+            localstatedir: dir("/home/user/.local/share"),
+            runstatedir: dir("/run/user/1000"),
+            includedir: dir("/home/user/.local/include"),
+            systemd_unitsdir: dir("/home/user/.config/systemd"),
+            bash_completionsdir: dir("/home/user/.local/share/bash-completion"),
+            elvish_completionsdir: dir("/home/user/.local/share/elvish/lib"),
+            kde_servicesdir: dir("/home/user/.local/share/kservices5"),
+            ..DirsConfig::user_config()
+        };
+        Dirs::new(dirs_config, false).unwrap()
+    }
+
+    fn options(system_install: bool) -> TargetOptions {
+        TargetOptions {
+            system_install,
+            man_compression: None,
+            enable_units: false,
+            update_mandb: false,
+            libexec_subdir: false,
+            only: Vec::new(),
+            skip: Vec::new(),
+            features: Vec::new(),
+            kernel_version: None,
+            polkit_owner: None,
+            load_apparmor: false,
+            load_selinux: false,
+        }
+    }
+
+    fn targets(
+        package: Package,
+        dirs: &Dirs,
+        options: &TargetOptions,
+    ) -> Vec<InstallTarget> {
+        let project = Project {
+            outputdir: None,
+            projectdir: Utf8PathBuf::from("/src/foo"),
+            sourcedir: None,
+        };
+        package
+            .targets(dirs, &project, &Version::new(0, 3, 0), options)
+            .unwrap()
+    }
+
+    #[test]
+    fn pixmap_icon_user_install() {
+        let package = package("icons:\n  - src: foo.png\n    pixmaps: true\n");
+        let targets = targets(package, &user_dirs(), &options(false));
+        assert_eq!(targets.len(), 1);
+        assert_eq!(
+            targets[0].destination,
+            Utf8PathBuf::from("/home/user/.local/share/pixmaps/")
+        );
+        assert!(targets[0].post_install.is_none());
+    }
+
+    #[test]
+    fn pkg_config_user_install() {
+        let package = package("pkg-config:\n  - foo.pc\n");
+        let targets = targets(package, &user_dirs(), &options(false));
+        assert_eq!(targets.len(), 1);
+        assert_eq!(
+            targets[0].destination,
+            Utf8PathBuf::from("/home/user/.local/share/pkgconfig/")
+        );
+    }
+
+    #[test]
+    fn libexec_subdir() {
+        let libexec = || package("libexec:\n  - foo-helper\n");
+        let mut options = options(true);
+        let flat = targets(libexec(), &system_dirs(), &options);
+        assert_eq!(
+            flat[0].destination,
+            Utf8PathBuf::from("/usr/local/libexec/")
+        );
+
+        options.libexec_subdir = true;
+        let namespaced = targets(libexec(), &system_dirs(), &options);
+        assert_eq!(
+            namespaced[0].destination,
+            Utf8PathBuf::from("/usr/local/libexec/foo/")
+        );
+    }
+
+    #[test]
+    fn completion_command_rename() {
+        let package = package(concat!(
+            "completions:\n",
+            "  bash:\n",
+            "    - src: completions/tool.bash\n",
+            "      command: tool\n",
+            "  zsh:\n",
+            "    - src: completions/tool.zsh\n",
+            "      command: tool\n",
+        ));
+        let targets = targets(package, &system_dirs(), &options(true));
+        let destinations: Vec<&str> = targets
+            .iter()
+            .map(|target| target.destination.as_str())
+            .collect();
+        assert_eq!(
+            destinations,
+            [
+                "/usr/local/share/bash-completion/completions/tool",
+                "/usr/local/share/zsh/site-functions/_tool",
+            ]
+        );
+    }
+
+    #[test]
+    fn polkit_rules_default_mode() {
+        let package = package("polkit-rules:\n  - 50-foo.rules\n");
+        let targets = targets(package, &system_dirs(), &options(true));
+        assert_eq!(targets.len(), 1);
+        assert_eq!(
+            targets[0].destination,
+            Utf8PathBuf::from("/usr/local/share/polkit-1/rules.d/")
+        );
+        assert_eq!(targets[0].mode, Some(0o644));
+        assert_eq!(targets[0].owner, None);
+    }
+
+    #[test]
+    fn kernel_modules_dir_ignores_prefix() {
+        let package = package("kernel-modules:\n  - mymod.ko\n");
+        let mut options = options(true);
+        options.kernel_version = Some("6.1.0-foo".to_string());
+        let targets = targets(package, &system_dirs(), &options);
+        assert_eq!(targets.len(), 1);
+        assert_eq!(
+            targets[0].destination,
+            Utf8PathBuf::from("/usr/lib/modules/6.1.0-foo/extra/")
+        );
+    }
+}
@@ -1,39 +1,157 @@
-use std::fs;
+use std::{
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use color_eyre::{eyre::Context, Result};
+use semver::Version;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
     dirs::Dirs,
-    utils::{append_destdir, write_to_file},
+    post_install::{EnabledUnit, LoadedPolicy},
+    utils::{append_destdir, create_dir_all_with_mode, write_to_file},
 };
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct InstalledFile {
     pub path: Utf8PathBuf,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub checksum: String,
     pub replace: bool,
+    // The target of the installed symlink
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlink: Option<Utf8PathBuf>,
+    // The install.yml section of the file, missing in the pkginfo written by
+    // older rinstall versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
 }
 
 impl InstalledFile {
     pub fn has_been_modified(&self) -> Result<bool> {
-        Ok(self.checksum
-            != blake3::hash(
-                &fs::read(&self.path)
-                    .with_context(|| format!("unable to read file {:?}", self.path))?,
-            )
+        if let Some(target) = &self.symlink {
+            return Ok(self
+                .path
+                .read_link_utf8()
+                .with_context(|| format!("unable to read symlink {:?}", self.path))?
+                != *target);
+        }
+        Ok(self.checksum != checksum(&self.path)?)
+    }
+
+    // Symlinks are checked without following them, so that the ones
+    // pointing to a removed file are removed too
+    pub fn exists(&self) -> bool {
+        if self.symlink.is_some() {
+            self.path.symlink_metadata().is_ok()
+        } else {
+            self.path.exists()
+        }
+    }
+}
+
+// The checksum of a file as recorded in the pkginfo
+pub fn checksum(path: &Utf8Path) -> Result<String> {
+    Ok(
+        blake3::hash(&fs::read(path).with_context(|| format!("unable to read file {:?}", path))?)
             .to_hex()
-            .to_string())
+            .to_string(),
+    )
+}
+
+// The SHA-256 checksum of a file, as printed by sha256sum
+pub fn sha256_checksum(path: &Utf8Path) -> Result<String> {
+    let contents = fs::read(path).with_context(|| format!("unable to read file {:?}", path))?;
+
+    Ok(Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Information about the installation, missing in the pkginfo written by
+// older rinstall versions
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PackageMetadata {
+    // The version of rinstall that installed the package
+    pub rinstall_version: String,
+    // The version of install.yml used by the package
+    pub spec_version: Version,
+    // The UTC time of the installation, in RFC 3339 format
+    pub installed_at: String,
+    pub system: bool,
+    // The version of the installed package, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl PackageMetadata {
+    pub fn new(
+        spec_version: &Version,
+        system: bool,
+        version: Option<String>,
+    ) -> Self {
+        Self {
+            rinstall_version: env!("CARGO_PKG_VERSION").to_string(),
+            spec_version: spec_version.clone(),
+            installed_at: utc_timestamp(SystemTime::now()),
+            system,
+            version,
+        }
     }
 }
 
+// Format a time as YYYY-MM-DDTHH:MM:SSZ
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, secs) = (secs / 86400, secs % 86400);
+    // Convert the days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct PackageInfo {
     #[serde(skip)]
     pub pkg_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<PackageMetadata>,
     pub path: Utf8PathBuf,
     pub files: Vec<InstalledFile>,
+    // The systemd units enabled after the installation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enabled_units: Vec<EnabledUnit>,
+    // The security policies loaded after the installation
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub loaded_policies: Vec<LoadedPolicy>,
+    // The directories created by the installation, removed when uninstalling
+    // the package if they are empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub directories: Vec<Utf8PathBuf>,
 }
 
 impl PackageInfo {
@@ -43,46 +161,127 @@ impl PackageInfo {
     ) -> Self {
         Self {
             pkg_name: pkg_name.to_string(),
-            path: dirs
-                .localstatedir
-                .join("rinstall")
-                .join(format!("{}.pkg", &pkg_name)),
+            metadata: None,
+            path: Self::dir(dirs).join(format!("{}.pkg", &pkg_name)),
             files: Vec::new(),
+            enabled_units: Vec::new(),
+            loaded_policies: Vec::new(),
+            directories: Vec::new(),
         }
     }
 
+    // The directory containing the pkginfo of all the installed packages
+    pub fn dir(dirs: &Dirs) -> Utf8PathBuf {
+        dirs.localstatedir.join("rinstall")
+    }
+
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        let mut pkg_info: Self = serde_yaml::from_str(
+            &fs::read_to_string(path).with_context(|| format!("unable to read file {:?}", path))?,
+        )
+        .with_context(|| format!("unable to parse pkginfo {:?}", path))?;
+        pkg_info.pkg_name = path.file_stem().unwrap_or_default().to_string();
+
+        Ok(pkg_info)
+    }
+
     pub fn add_file(
         &mut self,
         path: &Utf8Path,
         installed_path: &Utf8Path,
         replace: bool,
+        section: &str,
     ) -> Result<()> {
         let file = InstalledFile {
             path: Utf8Path::new("/").join(installed_path),
-            checksum: blake3::hash(
-                &fs::read(path).with_context(|| format!("unable to read file {:?}", path))?,
-            )
-            .to_hex()
-            .to_string(),
+            checksum: checksum(path)?,
             replace,
+            symlink: None,
+            section: Some(section.to_string()),
         };
 
-        self.files.push(file);
+        self.push_file(file);
 
         Ok(())
     }
 
+    pub fn add_symlink(
+        &mut self,
+        installed_path: &Utf8Path,
+        target: &Utf8Path,
+        section: &str,
+    ) {
+        self.push_file(InstalledFile {
+            path: Utf8Path::new("/").join(installed_path),
+            checksum: String::new(),
+            replace: true,
+            symlink: Some(target.to_path_buf()),
+            section: Some(section.to_string()),
+        });
+    }
+
+    pub fn add_directory(
+        &mut self,
+        installed_path: &Utf8Path,
+    ) {
+        let path = Utf8Path::new("/").join(installed_path);
+        if !self.directories.contains(&path) {
+            self.directories.push(path);
+        }
+    }
+
+    // Replace the entry of a file that has been installed again, e.g. when
+    // updating only some of the entries of a package
+    fn push_file(
+        &mut self,
+        file: InstalledFile,
+    ) {
+        self.files.retain(|installed| installed.path != file.path);
+        self.files.push(file);
+    }
+
+    // Keep the entry of a file recorded by the previous installation, e.g. a
+    // modified config that is not overwritten
+    pub fn keep_file(
+        &mut self,
+        previous_pkg_info: &PackageInfo,
+        installed_path: &Utf8Path,
+    ) {
+        let path = Utf8Path::new("/").join(installed_path);
+        if let Some(file) = previous_pkg_info
+            .files
+            .iter()
+            .find(|file| file.path == path)
+        {
+            self.push_file(file.clone());
+        }
+    }
+
+    // The recorded checksum of an installed file
+    pub fn file_checksum(
+        &self,
+        installed_path: &Utf8Path,
+    ) -> Option<&str> {
+        let path = Utf8Path::new("/").join(installed_path);
+        self.files
+            .iter()
+            .find(|file| file.path == path)
+            .map(|file| file.checksum.as_str())
+    }
+
     pub fn install(
         &self,
         destdir: Option<&str>,
+        dir_mode: u32,
     ) -> Result<()> {
         let path = append_destdir(&self.path, destdir);
-        fs::create_dir_all(path.parent().unwrap())
+        create_dir_all_with_mode(path.parent().unwrap(), dir_mode)
             .with_context(|| format!("unable to create parent directory for {:?}", path))?;
         write_to_file(
             &path,
-            &serde_yaml::to_string(self)
-                .with_context(|| format!("unable to serialize installation into {:?}", path))?,
+            serde_yaml::to_string(self)
+                .with_context(|| format!("unable to serialize installation into {:?}", path))?
+                .as_bytes(),
         )
         .with_context(|| format!("unable to write installation info in {:?}", path))?;
 
@@ -0,0 +1,190 @@
+use std::{io::ErrorKind, process::Command};
+
+use camino::Utf8PathBuf;
+use color_eyre::{eyre::Context, Result};
+use colored::Colorize;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::would;
+
+// A command that needs to be run after installing some files, like
+// refreshing a cache. They are only run for real installations, i.e. when
+// the destdir is not set
+#[derive(Clone, PartialEq, Eq)]
+pub enum PostInstall {
+    IconCache(Utf8PathBuf),
+    FontCache(Utf8PathBuf),
+    DesktopDatabase(Utf8PathBuf),
+    MimeDatabase(Utf8PathBuf),
+    // The kernel version of the installed modules
+    Depmod(String),
+    ManDatabase(Utf8PathBuf),
+    EnableUnit(EnabledUnit),
+    LoadPolicy(LoadedPolicy),
+}
+
+// BSD-like systems do not ship man-db, refresh the whatis database with makewhatis
+#[cfg(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+))]
+static MAN_DATABASE_COMMAND: (&str, &[&str]) = ("makewhatis", &[]);
+#[cfg(not(any(
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+static MAN_DATABASE_COMMAND: (&str, &[&str]) = ("mandb", &["-q"]);
+
+// A systemd unit enabled after the installation, recorded in the pkginfo
+// so that it can be disabled when uninstalling the package
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnabledUnit {
+    pub name: String,
+    pub scope: UnitScope,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitScope {
+    // A system unit
+    System,
+    // A user unit installed system-wide, enabled for all the users
+    Global,
+    // A user unit installed for the current user
+    User,
+}
+
+// A security policy loaded after the installation, recorded in the pkginfo
+// so that it can be unloaded when uninstalling the package
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadedPolicy {
+    pub kind: PolicyKind,
+    pub path: Utf8PathBuf,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolicyKind {
+    // A profile loaded into the kernel with apparmor_parser
+    AppArmor,
+    // A module added to the policy store with semodule
+    SELinux,
+}
+
+impl LoadedPolicy {
+    // Remove the policy from the kernel or the policy store, the AppArmor
+    // profile must still be installed
+    pub fn unload(
+        &self,
+        accept_changes: bool,
+    ) -> Result<()> {
+        let (program, args) = self.command(false);
+        run_command(program, &args, accept_changes)
+    }
+
+    fn command(
+        &self,
+        load: bool,
+    ) -> (&'static str, Vec<&str>) {
+        match (self.kind, load) {
+            (PolicyKind::AppArmor, true) => ("apparmor_parser", vec!["-r", self.path.as_str()]),
+            (PolicyKind::AppArmor, false) => ("apparmor_parser", vec!["-R", self.path.as_str()]),
+            (PolicyKind::SELinux, true) => ("semodule", vec!["-i", self.path.as_str()]),
+            // The modules are removed by name, i.e. the file name without .pp
+            (PolicyKind::SELinux, false) => (
+                "semodule",
+                vec!["-r", self.path.file_stem().unwrap_or_default()],
+            ),
+        }
+    }
+}
+
+impl EnabledUnit {
+    // Run systemctl enable/disable on the unit
+    pub fn systemctl(
+        &self,
+        action: &'static str,
+        accept_changes: bool,
+    ) -> Result<()> {
+        run_command("systemctl", &self.systemctl_args(action), accept_changes)
+    }
+
+    fn systemctl_args(
+        &self,
+        action: &'static str,
+    ) -> Vec<&str> {
+        match self.scope {
+            UnitScope::System => vec![action, &self.name],
+            UnitScope::Global => vec!["--global", action, &self.name],
+            UnitScope::User => vec!["--user", action, &self.name],
+        }
+    }
+}
+
+impl PostInstall {
+    fn command(&self) -> (&'static str, Vec<&str>) {
+        match self {
+            Self::IconCache(theme_dir) => (
+                "gtk-update-icon-cache",
+                vec!["-f", "-t", theme_dir.as_str()],
+            ),
+            Self::FontCache(fonts_dir) => ("fc-cache", vec!["-f", fonts_dir.as_str()]),
+            Self::DesktopDatabase(applications_dir) => (
+                "update-desktop-database",
+                vec!["-q", applications_dir.as_str()],
+            ),
+            Self::MimeDatabase(mime_dir) => ("update-mime-database", vec![mime_dir.as_str()]),
+            Self::Depmod(kernel_version) => ("depmod", vec!["-a", kernel_version.as_str()]),
+            Self::ManDatabase(mandir) => {
+                let (program, args) = MAN_DATABASE_COMMAND;
+                (program, [args, &[mandir.as_str()]].concat())
+            }
+            Self::EnableUnit(unit) => ("systemctl", unit.systemctl_args("enable")),
+            Self::LoadPolicy(policy) => policy.command(true),
+        }
+    }
+
+    pub fn run(
+        &self,
+        accept_changes: bool,
+    ) -> Result<()> {
+        let (program, args) = self.command();
+        run_command(program, &args, accept_changes)
+    }
+}
+
+// Run a command, only printing it when the changes are not accepted.
+// A missing program or a failure are not fatal, as the files have already
+// been installed
+fn run_command(
+    program: &str,
+    args: &[&str],
+    accept_changes: bool,
+) -> Result<()> {
+    let command_line = format!("{} {}", program, args.join(" "));
+    if !accept_changes {
+        info!("{} {}", would("run"), command_line.purple().bold());
+        return Ok(());
+    }
+
+    info!("Running {}", command_line.purple().bold());
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("{} failed with {}", command_line.yellow().bold(), status),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            warn!(
+                "{} has not been found, skipping {}",
+                program.yellow().bold(),
+                command_line.bright_black()
+            );
+        }
+        Err(err) => return Err(err).with_context(|| format!("unable to run {}", command_line)),
+    }
+
+    Ok(())
+}
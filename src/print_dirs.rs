@@ -0,0 +1,29 @@
+#[derive(Args, Clone)]
+pub struct PrintDirs {
+    #[clap(help = "Path to the rinstall.yml configuration", from_global)]
+    pub config: Option<String>,
+    #[clap(
+        long = "system",
+        help = "Print the directories used for a system-wide installation"
+    )]
+    pub system: bool,
+    #[clap(
+        long = "user",
+        help = "Print the directories used for a user installation (the default)",
+        conflicts_with = "system"
+    )]
+    pub user: bool,
+    #[clap(
+        long = "dump-config",
+        help = "Print the merged configuration as YAML instead of the directories"
+    )]
+    pub dump_config: bool,
+    #[clap(
+        long,
+        requires = "dump_config",
+        help = "Keep the placeholders in the configuration printed by --dump-config"
+    )]
+    pub unexpanded: bool,
+    #[clap(flatten, next_help_heading = "DIRECTORIES")]
+    pub dirs: DirsConfig,
+}
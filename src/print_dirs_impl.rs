@@ -0,0 +1,73 @@
+use clap::Args;
+use color_eyre::{eyre::Context, Result};
+
+use crate::{dirs::Dirs, dirs_config_impl::DirsConfig};
+
+include!("print_dirs.rs");
+
+impl PrintDirs {
+    pub fn run(&self) -> Result<()> {
+        if self.dump_config {
+            return self.dump_config();
+        }
+
+        let dirs_config = DirsConfig::load(self.config.as_deref(), self.system, None, &self.dirs)?;
+        let dirs = Dirs::new(dirs_config, self.system).context("unable to create dirs")?;
+
+        macro_rules! print_dirs {
+            ( $($var:ident),* ) => {
+                $(
+                    println!("{} = {}", stringify!($var), dirs.$var);
+                )*
+            };
+        }
+        macro_rules! print_dirs_opt {
+            ( $($var:ident),* ) => {
+                $(
+                    if let Some(dir) = &dirs.$var {
+                        println!("{} = {}", stringify!($var), dir);
+                    }
+                )*
+            };
+        }
+
+        print_dirs_opt!(prefix, exec_prefix);
+        print_dirs!(bindir);
+        print_dirs_opt!(sbindir);
+        print_dirs!(
+            libdir,
+            libexecdir,
+            datarootdir,
+            datadir,
+            sysconfdir,
+            localstatedir,
+            runstatedir
+        );
+        print_dirs_opt!(includedir, docdir, mandir, pam_modulesdir);
+        print_dirs!(systemd_unitsdir);
+        print_dirs_opt!(
+            bash_completionsdir,
+            elvish_completionsdir,
+            fish_completionsdir,
+            zsh_completionsdir
+        );
+        print_dirs!(kde_servicesdir);
+        print_dirs_opt!(qt_pluginsdir);
+
+        Ok(())
+    }
+
+    fn dump_config(&self) -> Result<()> {
+        let mut dirs_config =
+            DirsConfig::merged(self.config.as_deref(), self.system, None, &self.dirs)?;
+        if !self.unexpanded {
+            dirs_config.replace_placeholders(self.system)?;
+        }
+        print!(
+            "{}",
+            serde_yaml::to_string(&dirs_config).context("unable to serialize the configuration")?
+        );
+
+        Ok(())
+    }
+}
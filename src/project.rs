@@ -1,7 +1,12 @@
 use std::{env, os::unix::process::CommandExt, process::Command};
 
 use camino::{Utf8Path, Utf8PathBuf};
-use color_eyre::{eyre::Context, Result};
+use color_eyre::{
+    eyre::{ensure, Context},
+    Result,
+};
+use colored::Colorize;
+use log::{info, warn};
 
 // Contains data about the project that will be installed
 // It doesn't refer to the system and the actual installation directories
@@ -9,29 +14,205 @@ use color_eyre::{eyre::Context, Result};
 pub struct Project {
     pub outputdir: Option<Utf8PathBuf>,
     pub projectdir: Utf8PathBuf,
+    // The staging directory given with --source-dir, searched before the projectdir
+    pub sourcedir: Option<Utf8PathBuf>,
 }
 
 use crate::package::Type;
 
+static PROJECTDIR_NEEDLE: &str = "$PROJECTDIR";
+static OUTPUTDIR_NEEDLE: &str = "$OUTPUTDIR";
+static SOURCEDIR_NEEDLE: &str = "$SOURCEDIR";
+
 impl Project {
     pub fn new_from_type(
         project_type: Type,
         projectdir: &Utf8Path,
         is_release_tarball: bool,
         rust_debug_target: bool,
+        sourcedir: Option<&Utf8Path>,
     ) -> Result<Self> {
+        let outputdir = if is_release_tarball {
+            None
+        } else {
+            match project_type {
+                Type::Rust => Some(get_target_dir_for_rust(projectdir, rust_debug_target)?),
+                Type::Default | Type::Custom => None,
+            }
+        };
+        // The packages installing only files of the project directory, e.g. the docs,
+        // do not need to be built; the sources missing because of it fail later
+        if let Some(outputdir) = outputdir.as_ref().filter(|outputdir| !outputdir.exists()) {
+            warn!(
+                "the output directory {} does not exist, build the project by running {}",
+                outputdir.as_str().yellow().bold(),
+                cargo_build_command(rust_debug_target)
+                    .join(" ")
+                    .bright_black()
+                    .italic()
+            );
+        }
+
+        if let Some(sourcedir) = sourcedir {
+            ensure!(
+                sourcedir.is_dir(),
+                "the source directory {:?} does not exist",
+                sourcedir
+            );
+        }
+
         Ok(Self {
-            outputdir: if is_release_tarball {
-                None
-            } else {
-                match project_type {
-                    Type::Rust => Some(get_target_dir_for_rust(projectdir, rust_debug_target)?),
-                    Type::Default | Type::Custom => None,
-                }
-            },
+            outputdir,
             projectdir: projectdir.to_path_buf(),
+            sourcedir: sourcedir.map(Utf8Path::to_path_buf),
         })
     }
+
+    // A release tarball contains all the files in the top directory, either
+    // marked by a .tarball file or without any cargo build artifacts
+    pub fn is_release_tarball(
+        projectdir: &Utf8Path,
+        forced: bool,
+    ) -> bool {
+        forced
+            || projectdir.join(".tarball").exists()
+            || (!projectdir.join("target").exists() && !projectdir.join("Cargo.toml").exists())
+    }
+
+    // Check that the source of an entry does not point outside the project,
+    // e.g. by using ../ in install.yml
+    pub fn check_source(
+        &self,
+        source: &Utf8Path,
+        strict_sources: bool,
+    ) -> Result<()> {
+        let source_path = self.source_path(source);
+        // Missing sources are reported when listing the files to install
+        let Ok(source_path) = source_path.canonicalize_utf8() else {
+            return Ok(());
+        };
+        let is_inside = [
+            Some(&self.projectdir),
+            self.outputdir.as_ref(),
+            self.sourcedir.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|root| root.canonicalize_utf8().ok())
+        .any(|root| source_path.starts_with(root));
+        if !is_inside {
+            ensure!(
+                !strict_sources,
+                "the source {:?} points outside of the project directory",
+                source
+            );
+            warn!(
+                "the source {} points outside of the project directory, to {}",
+                source.as_str().yellow().bold(),
+                source_path.as_str().bright_black()
+            );
+        }
+
+        Ok(())
+    }
+
+    // Build a rust project using the same profile used to search for the files
+    pub fn build_rust(
+        projectdir: &Utf8Path,
+        rust_debug_target: bool,
+    ) -> Result<()> {
+        let command = cargo_build_command(rust_debug_target);
+        info!("Running {}", command.join(" ").purple().bold());
+        let status = Command::new(command[0])
+            .args(&command[1..])
+            .current_dir(projectdir)
+            .status()
+            .with_context(|| format!("unable to run `{}`", command.join(" ")))?;
+        ensure!(
+            status.success(),
+            "`{}` failed with {}",
+            command.join(" "),
+            status
+        );
+
+        Ok(())
+    }
+
+    // The output directory when it has not been created by building the project
+    pub fn missing_outputdir(&self) -> Option<&Utf8Path> {
+        self.outputdir
+            .as_deref()
+            .filter(|outputdir| !outputdir.exists())
+    }
+
+    // Get the path of a source file listed in install.yml
+    pub fn source_path(
+        &self,
+        source: &Utf8Path,
+    ) -> Utf8PathBuf {
+        // The source is using the needle to force it to be in the projectdir
+        if let Ok(source) = source.strip_prefix(PROJECTDIR_NEEDLE) {
+            self.projectdir.join(source)
+        } else if let Ok(source) = source.strip_prefix(OUTPUTDIR_NEEDLE) {
+            // The source is using the needle to force it to be in the outputdir,
+            // projects without one (e.g. release tarballs) have everything in the projectdir
+            self.outputdir
+                .as_ref()
+                .unwrap_or(&self.projectdir)
+                .join(source)
+        } else if let Ok(source) = source.strip_prefix(SOURCEDIR_NEEDLE) {
+            // Same for the sourcedir, that is the projectdir without --source-dir
+            self.sourcedir
+                .as_ref()
+                .unwrap_or(&self.projectdir)
+                .join(source)
+        } else {
+            // Use the first of outputdir and sourcedir containing the source,
+            // otherwise we use project_dir
+            [self.outputdir.as_ref(), self.sourcedir.as_ref()]
+                .into_iter()
+                .flatten()
+                .map(|dir| dir.join(source))
+                .find(|source| source.exists())
+                .unwrap_or_else(|| self.projectdir.join(source))
+        }
+    }
+}
+
+// Get the id of the user that invoked sudo, falling back to the current one
+fn sudo_id(
+    var: &str,
+    current_id: u32,
+) -> u32 {
+    parse_sudo_id(var, env::var(var).ok(), current_id)
+}
+
+// Parse the value of a sudo variable, warning when it is not a valid id
+fn parse_sudo_id(
+    var: &str,
+    value: Option<String>,
+    current_id: u32,
+) -> u32 {
+    match value {
+        Some(id) => id.parse::<u32>().unwrap_or_else(|_| {
+            warn!(
+                "{} contains an invalid id '{}', using {} instead",
+                var.bright_black(),
+                id.yellow(),
+                current_id
+            );
+            current_id
+        }),
+        None => current_id,
+    }
+}
+
+fn cargo_build_command(rust_debug_target: bool) -> Vec<&'static str> {
+    if rust_debug_target {
+        vec!["cargo", "build"]
+    } else {
+        vec!["cargo", "build", "--release"]
+    }
 }
 
 fn get_target_dir_for_rust(
@@ -45,7 +226,7 @@ fn get_target_dir_for_rust(
             && Command::new("cargo")
                 .current_dir(projectdir)
                 .output()
-                .map_or(false, |output| output.status.success())
+                .is_ok_and(|output| output.status.success())
         {
             Utf8PathBuf::from(
                 json::parse(&String::from_utf8_lossy(
@@ -56,15 +237,9 @@ fn get_target_dir_for_rust(
                             // cargo metadata only works when running as the current user that has built
                             // the project. Otherwise it will use metadata for the root user and
                             // it is almost never what we want
-                            env::var("SUDO_UID").map_or(unsafe { libc::getuid() }, |uid| {
-                                uid.parse::<u32>().unwrap()
-                            }),
-                        )
-                        .gid(
-                            env::var("SUDO_GID").map_or(unsafe { libc::getgid() }, |gid| {
-                                gid.parse::<u32>().unwrap()
-                            }),
+                            sudo_id("SUDO_UID", unsafe { libc::getuid() }),
                         )
+                        .gid(sudo_id("SUDO_GID", unsafe { libc::getgid() }))
                         .output()
                         .context("unable to run `cargo metadata`")?
                         .stdout,
@@ -82,3 +257,63 @@ fn get_target_dir_for_rust(
         }),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use super::*;
+
+    // A project directory with a file inside and one next to it
+    fn project_with_escape(name: &str) -> (Project, Utf8PathBuf) {
+        let root = Utf8PathBuf::from_path_buf(env::temp_dir())
+            .unwrap()
+            .join(format!("rinstall-{}-{}", name, process::id()));
+        let projectdir = root.join("project");
+        fs::create_dir_all(&projectdir).unwrap();
+        fs::write(projectdir.join("foo"), "foo").unwrap();
+        fs::write(root.join("escape"), "escape").unwrap();
+        let project = Project {
+            outputdir: None,
+            projectdir,
+            sourcedir: None,
+        };
+        (project, root)
+    }
+
+    #[test]
+    fn check_source_inside_project() {
+        let (project, root) = project_with_escape("check-source-inside");
+        assert!(project.check_source(Utf8Path::new("foo"), true).is_ok());
+        assert!(project
+            .check_source(Utf8Path::new("$PROJECTDIR/foo"), true)
+            .is_ok());
+        // The missing sources are reported later
+        assert!(project.check_source(Utf8Path::new("missing"), true).is_ok());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn check_source_escape() {
+        let (project, root) = project_with_escape("check-source-escape");
+        assert!(project
+            .check_source(Utf8Path::new("../escape"), false)
+            .is_ok());
+        assert!(project
+            .check_source(Utf8Path::new("../escape"), true)
+            .is_err());
+        assert!(project
+            .check_source(Utf8Path::new("$PROJECTDIR/../escape"), true)
+            .is_err());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn sudo_id_invalid() {
+        let sudo_uid =
+            |value: Option<&str>| parse_sudo_id("SUDO_UID", value.map(String::from), 1000);
+        assert_eq!(sudo_uid(Some("not-a-uid")), 1000);
+        assert_eq!(sudo_uid(Some("1001")), 1001);
+        assert_eq!(sudo_uid(None), 1000);
+    }
+}
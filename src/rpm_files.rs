@@ -0,0 +1,89 @@
+#[derive(Args, Clone)]
+pub struct GenerateRpmFiles {
+    #[clap(help = "Path to the rinstall.yml configuration", from_global)]
+    pub config: Option<String>,
+    #[clap(
+        long = "system",
+        help = "Generate the list for a system-wide installation"
+    )]
+    pub system: bool,
+    #[clap(
+        long = "user",
+        help = "Generate the list for a user installation (the default)",
+        conflicts_with = "system"
+    )]
+    pub user: bool,
+    #[clap(
+        long,
+        help = concat!("Use the generated binaries and libraries from the",
+                       " debug profile (only effective for rust projects)")
+    )]
+    pub rust_debug_target: bool,
+    #[clap(
+        long,
+        help = concat!("Take all the files from the project directory, as in a",
+                       " release tarball (detected when there is no target/ and no Cargo.toml)")
+    )]
+    pub release_tarball: bool,
+    #[clap(
+        long = "source-dir",
+        help = concat!("Search the sources in this directory before the project directory,",
+                       " e.g. a staging directory; $SOURCEDIR refers to it")
+    )]
+    pub source_dir: Option<std::path::PathBuf>,
+    #[clap(
+        long,
+        help = "Install the libexec files into a subdirectory named after the package"
+    )]
+    pub libexec_subdir: bool,
+    #[clap(
+        long = "kernel-version",
+        help = concat!("The kernel version of the directory where the kernel modules are",
+                       " installed [default: the running kernel, as in uname -r]")
+    )]
+    pub kernel_version: Option<String>,
+    #[clap(
+        long = "polkit-owner",
+        value_name = "USER[:GROUP]",
+        help = "The owner of the installed polkit rules, e.g. polkitd:root"
+    )]
+    pub polkit_owner: Option<String>,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "Enable the given comma-separated features, installing the entries that depend on them"
+    )]
+    pub features: Vec<String>,
+    #[clap(
+        long = "skip-pkginfo",
+        help = "Do not list the rinstall pkginfo"
+    )]
+    pub skip_pkg_info: bool,
+    #[clap(
+        long = "prefix-relative",
+        help = "Replace the prefix of the files with the %{_prefix} macro"
+    )]
+    pub prefix_relative: bool,
+    #[clap(
+        short = 'P',
+        long,
+        help = "Path to the directory containing the project to install",
+        default_value_os_t = std::env::current_dir()
+            .expect("unable to get current directory"),
+    )]
+    pub package_dir: std::path::PathBuf,
+    #[clap(
+        long,
+        help = concat!("Path to the file describing the packages, used instead of the",
+                       " install.yml in the package directory")
+    )]
+    pub package_file: Option<std::path::PathBuf>,
+    #[clap(
+        short = 'p',
+        long = "pkgs",
+        help = "List of packages whose files are printed, separated by a comma"
+    )]
+    pub packages: Vec<String>,
+    #[clap(flatten, next_help_heading = "DIRECTORIES")]
+    pub dirs: DirsConfig,
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Args;
+use color_eyre::{eyre::Context, Result};
+
+use crate::{
+    dirs::Dirs, dirs_config_impl::DirsConfig, install_spec::InstallSpec,
+    install_target::InstallTarget, package::TargetOptions, package_info::PackageInfo,
+    project::Project,
+};
+
+include!("rpm_files.rs");
+
+impl GenerateRpmFiles {
+    pub fn run(self) -> Result<()> {
+        let install_spec = InstallSpec::load(
+            Utf8Path::from_path(&self.package_dir).unwrap(),
+            self.package_file
+                .as_deref()
+                .map(|file| Utf8Path::from_path(file).unwrap()),
+        )?;
+
+        let is_release_tarball = Project::is_release_tarball(
+            Utf8Path::from_path(&self.package_dir).unwrap(),
+            self.release_tarball,
+        );
+        let version = install_spec.version.clone();
+
+        for package in install_spec.packages(&self.packages) {
+            let dirs_config = DirsConfig::load(
+                self.config.as_deref(),
+                self.system,
+                package.dirs.clone(),
+                &self.dirs,
+            )?;
+            let dirs = Dirs::new(dirs_config, self.system).context("unable to create dirs")?;
+            let name = package.name.clone().unwrap();
+            let project = Project::new_from_type(
+                package.project_type(),
+                Utf8Path::from_path(&self.package_dir).unwrap(),
+                is_release_tarball,
+                self.rust_debug_target,
+                self.source_dir
+                    .as_deref()
+                    .map(|dir| Utf8Path::from_path(dir).unwrap()),
+            )?;
+
+            let options = TargetOptions {
+                system_install: self.system,
+                man_compression: None,
+                enable_units: false,
+                update_mandb: false,
+                libexec_subdir: self.libexec_subdir,
+                only: Vec::new(),
+                skip: Vec::new(),
+                features: self.features.clone(),
+                kernel_version: self.kernel_version.clone(),
+                polkit_owner: self.polkit_owner.clone(),
+                load_apparmor: false,
+                load_selinux: false,
+            };
+            let package_dirs = package.package_dirs(&dirs, &options);
+            let mut owned_dirs = BTreeSet::new();
+            for target in package.targets(&dirs, &project, &version, &options)? {
+                for (_, destination) in target.files(&project)? {
+                    let path = self.rpm_path(&destination, &dirs);
+                    if let Some(attr) = rpm_attr(&target) {
+                        print!("{} ", attr);
+                    }
+                    match target.section {
+                        "config" => println!("%config(noreplace) {}", path),
+                        "user-config" => println!("%config {}", path),
+                        "docs" => println!("%doc {}", path),
+                        "licenses" => println!("%license {}", path),
+                        // Man pages might get compressed after the installation
+                        "man" => println!("%doc {}*", path),
+                        _ => println!("{}", path),
+                    }
+                    for alias in target.alias_paths(&destination) {
+                        println!("%doc {}*", self.rpm_path(&alias, &dirs));
+                    }
+
+                    for package_dir in &package_dirs {
+                        owned_dirs.extend(
+                            destination
+                                .ancestors()
+                                .skip(1)
+                                .take_while(|dir| dir.starts_with(package_dir))
+                                .map(Utf8Path::to_path_buf),
+                        );
+                    }
+                }
+            }
+            for dir in owned_dirs {
+                println!("%dir {}", self.rpm_path(&dir, &dirs));
+            }
+
+            if !self.skip_pkg_info {
+                println!(
+                    "{}",
+                    self.rpm_path(&PackageInfo::new(&name, &dirs).path, &dirs)
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // The path as written in the %files section of the spec file, the destdir
+    // is never part of it
+    fn rpm_path(
+        &self,
+        path: &Utf8Path,
+        dirs: &Dirs,
+    ) -> Utf8PathBuf {
+        match &dirs.prefix {
+            Some(prefix) if self.prefix_relative => path
+                .strip_prefix(prefix)
+                .map_or(path.to_path_buf(), |path| {
+                    Utf8Path::new("%{_prefix}").join(path)
+                }),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+// The %attr directive of the files installed with a fixed mode or owner
+fn rpm_attr(target: &InstallTarget) -> Option<String> {
+    if target.mode.is_none() && target.owner.is_none() {
+        return None;
+    }
+    let mode = target
+        .mode
+        .map_or("-".to_string(), |mode| format!("{:04o}", mode));
+    let (user, group) = target.owner.as_deref().map_or(("", ""), |owner| {
+        owner.split_once(':').unwrap_or((owner, ""))
+    });
+    let or_default = |name: &str| if name.is_empty() { "-" } else { name }.to_string();
+
+    Some(format!(
+        "%attr({},{},{})",
+        mode,
+        or_default(user),
+        or_default(group)
+    ))
+}
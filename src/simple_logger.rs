@@ -1,8 +1,47 @@
+use std::{ops::Deref, sync::Mutex};
+
 use colored::Colorize;
+use indicatif::ProgressBar;
 use log::{Level, Metadata, Record};
 
+use crate::opts_impl::OutputFormat;
+
+// The progress bar currently drawn, the messages are printed above it
+static PROGRESS_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+// Draw a progress bar until the guard is dropped
+pub struct ProgressBarGuard(ProgressBar);
+
+impl ProgressBarGuard {
+    pub fn new(progress_bar: ProgressBar) -> Self {
+        if let Ok(mut current) = PROGRESS_BAR.lock() {
+            *current = Some(progress_bar.clone());
+        }
+        Self(progress_bar)
+    }
+}
+
+impl Deref for ProgressBarGuard {
+    type Target = ProgressBar;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for ProgressBarGuard {
+    fn drop(&mut self) {
+        self.0.finish_and_clear();
+        if let Ok(mut current) = PROGRESS_BAR.lock() {
+            *current = None;
+        }
+    }
+}
+
 pub struct SimpleLogger {
     pub quiet: bool,
+    pub verbose: bool,
+    pub format: OutputFormat,
 }
 
 impl log::Log for SimpleLogger {
@@ -10,22 +49,55 @@ impl log::Log for SimpleLogger {
         &self,
         metadata: &Metadata,
     ) -> bool {
-        metadata.level() <= Level::Warn || !self.quiet
+        match metadata.level() {
+            Level::Error | Level::Warn => true,
+            Level::Info => !self.quiet,
+            Level::Debug | Level::Trace => self.verbose,
+        }
     }
 
     fn log(
         &self,
         record: &Record,
     ) {
-        if self.enabled(record.metadata()) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let progress_bar = PROGRESS_BAR
+            .lock()
+            .ok()
+            .and_then(|progress_bar| progress_bar.clone());
+        match progress_bar {
+            Some(progress_bar) => progress_bar.suspend(|| self.print(record)),
+            None => self.print(record),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+impl SimpleLogger {
+    fn print(
+        &self,
+        record: &Record,
+    ) {
+        if self.format == OutputFormat::Json {
+            let line = json::object! {
+                level: record.level().as_str().to_lowercase(),
+                message: record.args().to_string(),
+            }
+            .dump();
+            match record.level() {
+                Level::Error | Level::Warn => eprintln!("{}", line),
+                Level::Info | Level::Debug | Level::Trace => println!("{}", line),
+            }
+        } else {
             match record.level() {
                 Level::Error => eprintln!("{}: {}", "ERROR".red().bold(), record.args()),
                 Level::Warn => eprintln!("{}: {}", "WARNING".yellow().bold(), record.args()),
-                Level::Info => println!("{}", record.args()),
-                Level::Debug | Level::Trace => unreachable!(),
+                Level::Info | Level::Debug => println!("{}", record.args()),
+                Level::Trace => unreachable!(),
             }
         }
     }
-
-    fn flush(&self) {}
 }
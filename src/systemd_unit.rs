@@ -0,0 +1,129 @@
+use std::{collections::BTreeMap, io::ErrorKind, process::Command, str::FromStr};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use color_eyre::{
+    eyre::{ensure, Context},
+    Result,
+};
+use log::warn;
+use serde::Deserialize;
+use void::Void;
+
+use crate::{
+    install_cmd_impl::ValidationWarnings, install_entry::InstallEntry, templating::TemplatingMode,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemdUnit {
+    #[serde(rename(deserialize = "src"))]
+    pub source: Utf8PathBuf,
+    #[serde(rename(deserialize = "dst"))]
+    pub destination: Option<Utf8PathBuf>,
+    #[serde(default, rename(deserialize = "tmpl"))]
+    pub templating: Option<TemplatingMode>,
+    // Run systemctl enable after installing the unit
+    #[serde(default)]
+    pub enable: bool,
+    // Only install the entry when this feature is enabled with --features
+    #[serde(default)]
+    pub when: Option<String>,
+    // Template variables of this entry only, overriding the directories
+    #[serde(default)]
+    pub vars: BTreeMap<String, String>,
+}
+
+impl SystemdUnit {
+    const fn new_with_source(source: Utf8PathBuf) -> Self {
+        Self {
+            source,
+            destination: None,
+            templating: None,
+            enable: false,
+            when: None,
+            vars: BTreeMap::new(),
+        }
+    }
+
+    // The name of the installed unit, None when installing a directory.
+    // Unit names always have a suffix, e.g. foo.service
+    pub fn name(&self) -> Option<&str> {
+        let path = self
+            .destination
+            .as_ref()
+            .filter(|destination| !destination.as_str().ends_with('/'))
+            .unwrap_or(&self.source);
+        if path.as_str().ends_with('/') || path.extension().is_none() {
+            None
+        } else {
+            path.file_name()
+        }
+    }
+}
+
+impl From<SystemdUnit> for InstallEntry {
+    fn from(unit: SystemdUnit) -> Self {
+        Self {
+            source: unit.source,
+            destination: unit.destination,
+            templating: unit.templating,
+            rename: BTreeMap::new(),
+            when: unit.when,
+            vars: unit.vars,
+        }
+    }
+}
+
+impl FromStr for SystemdUnit {
+    // This implementation of `from_str` can never fail, so use the impossible
+    // `Void` type as the error type.
+    type Err = Void;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new_with_source(Utf8PathBuf::from(s)))
+    }
+}
+
+// Check a unit file by running systemd-analyze verify on it, return false
+// if systemd-analyze has not been found
+pub fn verify_unit(
+    path: &Utf8Path,
+    user: bool,
+    warnings: ValidationWarnings,
+) -> Result<bool> {
+    let mut command = Command::new("systemd-analyze");
+    if user {
+        command.arg("--user");
+    }
+    let output = match command.arg("verify").arg(path).output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).context("unable to run systemd-analyze"),
+    };
+    let messages = [output.stdout, output.stderr]
+        .iter()
+        .map(|output| String::from_utf8_lossy(output).trim().to_string())
+        .filter(|output| !output.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n");
+    ensure!(
+        output.status.success(),
+        "the systemd unit {:?} is not valid, add --no-validate to skip the validation:\n{}",
+        path,
+        messages
+    );
+    if !messages.is_empty() {
+        ensure!(
+            warnings == ValidationWarnings::Warn,
+            "the validation of the systemd unit {:?} printed some warnings:\n{}",
+            path,
+            messages
+        );
+        // systemd-analyze already prefixes the messages with the unit path
+        for message in messages.lines() {
+            warn!("{}", message);
+        }
+    }
+
+    Ok(true)
+}
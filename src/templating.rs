@@ -1,13 +1,74 @@
-use std::fs;
+use std::{collections::BTreeMap, fs};
 
 use camino::Utf8Path;
 use color_eyre::{
     eyre::{ensure, Context, ContextCompat},
     Result,
 };
+use handlebars::{no_escape, Handlebars};
+use serde::{de, Deserialize, Deserializer};
 
 use crate::Dirs;
 
+// How the placeholders of a file are replaced, set with tmpl in the entries
+// and with templating in the packages
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum TemplatingMode {
+    #[default]
+    Disabled,
+    // Replace the @dir@ placeholders
+    Replace,
+    // Render the file as a handlebars template, with the directories as variables
+    Handlebars,
+}
+
+impl TemplatingMode {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            TemplatingMode::Disabled => "disabled",
+            TemplatingMode::Replace => "replace",
+            TemplatingMode::Handlebars => "handlebars",
+        }
+    }
+}
+
+// Allow both a boolean (true for the simple replacement) and the name of the engine
+impl<'de> Deserialize<'de> for TemplatingMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Enabled(bool),
+            Engine(String),
+        }
+
+        match Value::deserialize(deserializer)? {
+            Value::Enabled(true) => Ok(Self::Replace),
+            Value::Enabled(false) => Ok(Self::Disabled),
+            Value::Engine(engine) if engine == "replace" => Ok(Self::Replace),
+            Value::Engine(engine) if engine == "handlebars" => Ok(Self::Handlebars),
+            Value::Engine(engine) => Err(de::Error::custom(format!(
+                "unknown templating engine '{}', expected a boolean, replace or handlebars",
+                engine
+            ))),
+        }
+    }
+}
+
+// The variables used when templating an entry, the ones given with --define
+// overridden by the vars of the entry
+pub fn template_vars(
+    defines: &[(String, String)],
+    vars: &BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut template_vars: BTreeMap<String, String> = defines.iter().cloned().collect();
+    template_vars.extend(vars.clone());
+    template_vars
+}
+
 pub struct Templating {
     pub contents: String,
 }
@@ -15,7 +76,7 @@ pub struct Templating {
 impl Templating {
     pub fn new(source: &Utf8Path) -> Result<Self> {
         Ok(Self {
-            contents: fs::read_to_string(&source)
+            contents: fs::read_to_string(source)
                 .with_context(|| format!("unable to read file {:?}", source))?,
         })
     }
@@ -23,7 +84,27 @@ impl Templating {
     pub fn apply(
         &mut self,
         dirs: &Dirs,
+        mode: TemplatingMode,
+        vars: &BTreeMap<String, String>,
     ) -> Result<()> {
+        match mode {
+            TemplatingMode::Disabled => Ok(()),
+            TemplatingMode::Replace => self.replace(dirs, vars),
+            TemplatingMode::Handlebars => self.render(dirs, vars),
+        }
+    }
+
+    // The variables of the entry are replaced first, so that they take
+    // precedence over the directories, e.g. @port@ or @bindir@
+    fn replace(
+        &mut self,
+        dirs: &Dirs,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        for (name, value) in vars {
+            self.contents = self.contents.replace(&format!("@{}@", name), value);
+        }
+
         macro_rules! replace_impl {
             ( $dir:expr, $needle:literal ) => {
                 self.contents = self.contents.replace(
@@ -73,4 +154,136 @@ impl Templating {
 
         Ok(())
     }
+
+    // Render the contents with handlebars, using the directories and the variables
+    // of the entry, e.g. {{bindir}}. Using an unset directory is an error
+    fn render(
+        &mut self,
+        dirs: &Dirs,
+        vars: &BTreeMap<String, String>,
+    ) -> Result<()> {
+        let mut variables = BTreeMap::new();
+        macro_rules! insert {
+            ( $($dir:ident),* ) => {
+                $( variables.insert(stringify!($dir), dirs.$dir.as_str()); )*
+            };
+        }
+        macro_rules! insert_when_some {
+            ( $($dir:ident),* ) => {
+                $(
+                    if let Some($dir) = &dirs.$dir {
+                        variables.insert(stringify!($dir), $dir.as_str());
+                    }
+                )*
+            };
+        }
+        insert!(
+            bindir,
+            libdir,
+            libexecdir,
+            datarootdir,
+            datadir,
+            sysconfdir,
+            localstatedir,
+            runstatedir,
+            systemd_unitsdir
+        );
+        insert_when_some!(
+            prefix,
+            exec_prefix,
+            sbindir,
+            includedir,
+            docdir,
+            mandir,
+            pam_modulesdir
+        );
+        // The variables of the entry override the directories
+        variables.extend(
+            vars.iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        // The files are not html, keep the values as they are
+        handlebars.register_escape_fn(no_escape);
+        self.contents = handlebars
+            .render_template(&self.contents, &variables)
+            .context("unable to render the handlebars template")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirsConfig;
+
+    fn system_dirs() -> Dirs {
+        let mut dirs_config = DirsConfig::system_config();
+        dirs_config.replace_placeholders(true).unwrap();
+        Dirs::new(dirs_config, true).unwrap()
+    }
+
+    fn apply(
+        contents: &str,
+        mode: TemplatingMode,
+        defines: &[(String, String)],
+        vars: &BTreeMap<String, String>,
+    ) -> String {
+        let mut template = Templating {
+            contents: contents.to_string(),
+        };
+        template
+            .apply(&system_dirs(), mode, &template_vars(defines, vars))
+            .unwrap();
+        template.contents
+    }
+
+    #[test]
+    fn entry_vars_override_defines() {
+        let defines = [
+            ("port".to_string(), "8080".to_string()),
+            ("host".to_string(), "localhost".to_string()),
+        ];
+        let vars = BTreeMap::from([("port".to_string(), "8081".to_string())]);
+        assert_eq!(
+            apply("@host@:@port@", TemplatingMode::Replace, &defines, &vars),
+            "localhost:8081"
+        );
+        assert_eq!(
+            apply(
+                "{{host}}:{{port}}",
+                TemplatingMode::Handlebars,
+                &defines,
+                &vars
+            ),
+            "localhost:8081"
+        );
+    }
+
+    #[test]
+    fn defines_override_dirs() {
+        let defines = [("bindir".to_string(), "/opt/bin".to_string())];
+        let vars = BTreeMap::new();
+        assert_eq!(
+            apply(
+                "@bindir@ @libdir@",
+                TemplatingMode::Replace,
+                &defines,
+                &vars
+            ),
+            "/opt/bin /usr/local/lib"
+        );
+        assert_eq!(
+            apply(
+                "{{#if gui}}gui {{/if}}{{bindir}}",
+                TemplatingMode::Handlebars,
+                &[("gui".to_string(), "yes".to_string())],
+                &vars
+            ),
+            "gui /usr/local/bin"
+        );
+    }
 }
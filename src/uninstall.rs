@@ -13,6 +13,12 @@ pub struct Uninstall {
         help = "Accept the changes and perform the uninstallation"
     )]
     pub accept_changes: bool,
+    #[clap(
+        long = "dry-run",
+        help = "Only print the changes without performing the uninstallation",
+        conflicts_with = "accept_changes"
+    )]
+    pub dry_run: bool,
     #[clap(short = 'f', long = "force", help = "Force the uninstallation")]
     pub force: bool,
     #[clap(
@@ -21,6 +27,13 @@ pub struct Uninstall {
         global = true
     )]
     pub system: bool,
+    #[clap(
+        long = "user",
+        help = "Perform a user uninstallation, the default when --system is not given",
+        conflicts_with = "system",
+        global = true
+    )]
+    pub user: bool,
     #[clap(
         long,
         env,
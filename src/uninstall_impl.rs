@@ -1,4 +1,4 @@
-use std::fs;
+use std::{collections::HashSet, fs};
 
 use camino::{Utf8Path, Utf8PathBuf};
 use clap::Parser;
@@ -9,12 +9,18 @@ use color_eyre::{
 use colored::Colorize;
 use log::{info, warn};
 
-use crate::{dirs::Dirs, dirs_config_impl::DirsConfig, package_info::PackageInfo};
+use crate::{
+    dirs::Dirs,
+    dirs_config_impl::DirsConfig,
+    package_info::PackageInfo,
+    utils::{warn_implicit_user_mode, would},
+};
 
 include!("uninstall.rs");
 
 impl Uninstall {
     pub fn run(&self) -> Result<()> {
+        warn_implicit_user_mode(self.system, self.user);
         let mut opt_dirs = if self.system {
             DirsConfig::system_config()
         } else {
@@ -22,58 +28,113 @@ impl Uninstall {
         };
         opt_dirs.prefix = self.prefix.clone();
         opt_dirs.localstatedir = self.localstatedir.clone();
-        let dirs_config = DirsConfig::load(self.config.as_deref(), self.system, &opt_dirs)?;
+        let dirs_config = DirsConfig::load(self.config.as_deref(), self.system, None, &opt_dirs)?;
         let dirs = Dirs::new(dirs_config, self.system).context("unable to create dirs")?;
-        let dry_run = !self.accept_changes;
+        // --dry-run always prevents any change to the filesystem
+        let dry_run = self.dry_run || !self.accept_changes;
         for pkg in &self.packages {
             let pkg_info = if Utf8Path::new(&pkg).is_absolute() {
                 Utf8PathBuf::from(pkg)
             } else {
-                dirs.localstatedir
-                    .join("rinstall")
-                    .join(format!("{}.pkg", &pkg))
+                PackageInfo::dir(&dirs).join(format!("{}.pkg", &pkg))
             };
             ensure!(pkg_info.exists(), "package {} is not installed", &pkg);
-            let pkg_info: PackageInfo = serde_yaml::from_str(
-                &fs::read_to_string(&pkg_info)
-                    .with_context(|| format!("unable to read file {:?}", &pkg_info))?,
-            )?;
+            let pkg_info = PackageInfo::load(&pkg_info)?;
+
+            // Disable the units before removing them
+            for unit in &pkg_info.enabled_units {
+                unit.systemctl("disable", !dry_run)?;
+            }
+            // The AppArmor profiles are unloaded by reading them, before removing them
+            for policy in &pkg_info.loaded_policies {
+                policy.unload(!dry_run)?;
+            }
 
+            // The paths removed, or that would be removed, by the uninstallation
+            let mut removed = HashSet::new();
             for file in &pkg_info.files {
+                if !file.exists() {
+                    warn!(
+                        "file {} does not exist anymore",
+                        file.path.as_str().yellow().bold()
+                    );
+                    continue;
+                }
                 let modified = file.has_been_modified()?;
+                if modified && !self.force {
+                    if dry_run {
+                        warn!(
+                            "file {} has been modified and it won't be removed, add {} to remove it",
+                            file.path.as_str().yellow().bold(),
+                            "--force".bright_black().italic(),
+                        );
+                    } else {
+                        warn!(
+                            "Keeping modified file {}",
+                            file.path.as_str().yellow().bold()
+                        );
+                    }
+                    continue;
+                }
+
                 if dry_run {
-                    if file.replace && modified {
+                    if modified {
                         warn!(
-                            "file {} has been modified but it will be uninstalled anyway",
+                            "file {} has been modified but it will be removed anyway",
                             file.path.as_str().cyan().bold()
                         );
-                    } else if !file.replace && modified {
+                    } else {
+                        info!("{} {}", would("remove"), file.path.as_str().cyan().bold());
+                    }
+                } else {
+                    if modified {
                         warn!(
-                        "file {} has been modified but it won't be removed, add {} to remove it",
-                        file.path.as_str().yellow().bold(),
-                        "--force".bright_black().italic(),
-                    );
+                            "modified file {} has been uninstalled",
+                            file.path.as_str().cyan().bold(),
+                        );
                     } else {
-                        info!("Would remove {}", file.path.as_str().cyan().bold());
+                        info!("Removing {}", file.path.as_str().cyan().bold());
                     }
-                } else if !file.replace && modified && !self.force {
-                    info!("Keeping file {}", file.path.as_str().cyan().bold());
-                } else if modified && (file.replace || self.force) {
-                    warn!(
-                        "modified file {} has been uninstalled",
-                        file.path.as_str().cyan().bold(),
-                    );
                     fs::remove_file(&file.path)
                         .with_context(|| format!("unable to remove file {:?}", file.path))?;
+                }
+                removed.insert(file.path.clone());
+            }
+
+            // Remove the directories created by the installation that are now empty,
+            // starting from the innermost ones
+            let mut directories = pkg_info.directories.clone();
+            directories.sort_unstable_by(|a, b| b.cmp(a));
+            for dir in directories {
+                if !dir.is_dir() {
+                    continue;
+                }
+                let is_empty = fs::read_dir(&dir)
+                    .with_context(|| format!("unable to read directory {:?}", dir))?
+                    .filter_map(|entry| entry.ok())
+                    .all(|entry| {
+                        Utf8PathBuf::try_from(entry.path())
+                            .is_ok_and(|path| removed.contains(&path))
+                    });
+                if !is_empty {
+                    continue;
+                }
+                if dry_run {
+                    info!("{} {}", would("remove"), dir.as_str().cyan().bold());
                 } else {
-                    info!("Removing {}", file.path.as_str().cyan().bold());
-                    fs::remove_file(&file.path)
-                        .with_context(|| format!("unable to remove file {:?}", file.path))?;
+                    info!("Removing {}", dir.as_str().cyan().bold());
+                    fs::remove_dir(&dir)
+                        .with_context(|| format!("unable to remove directory {:?}", dir))?;
                 }
+                removed.insert(dir);
             }
 
             if dry_run {
-                info!("Would remove {}", pkg_info.path.as_str().cyan().bold());
+                info!(
+                    "{} {}",
+                    would("remove"),
+                    pkg_info.path.as_str().cyan().bold()
+                );
             } else {
                 info!("Removing {}", pkg_info.path.as_str().cyan().bold());
                 fs::remove_file(&pkg_info.path)
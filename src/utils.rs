@@ -1,10 +1,18 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Write},
+    ffi::CString,
+    fs::{self, File, Permissions},
+    io::{BufWriter, ErrorKind, IntoInnerError, Write},
+    os::unix::fs::{chown, PermissionsExt},
+    process,
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
-use color_eyre::{eyre::Context, Result};
+use color_eyre::{
+    eyre::{ensure, Context},
+    Result,
+};
+use colored::Colorize;
+use log::warn;
 
 pub fn append_destdir(
     destination: &Utf8Path,
@@ -13,7 +21,7 @@ pub fn append_destdir(
     destdir.map_or(destination.to_owned(), |destdir| {
         // join does not work when the argument (not the self) is an absolute path
         Utf8PathBuf::from({
-            let mut s = destdir.to_string();
+            let mut s = destdir.trim_end_matches('/').to_string();
             s.push_str(destination.as_str());
             s
         })
@@ -22,14 +30,232 @@ pub fn append_destdir(
 
 pub fn write_to_file(
     destination: &Utf8Path,
-    contents: &str,
+    contents: &[u8],
 ) -> Result<()> {
-    BufWriter::new(
-        File::create(&destination)
-            .with_context(|| format!("unable to create file {:?}", destination))?,
-    )
-    .write(contents.as_bytes())
-    .with_context(|| format!("unable to write to file {:?}", destination))?;
+    write_contents(destination, contents)?;
 
     Ok(())
 }
+
+// Write the contents and flush them, so that the write errors are not lost
+// when dropping the writer
+fn write_contents(
+    destination: &Utf8Path,
+    contents: &[u8],
+) -> Result<File> {
+    let mut writer = BufWriter::new(
+        File::create(destination)
+            .with_context(|| format!("unable to create file {:?}", destination))?,
+    );
+    writer
+        .write_all(contents)
+        .with_context(|| format!("unable to write to file {:?}", destination))?;
+    writer
+        .into_inner()
+        .map_err(IntoInnerError::into_error)
+        .with_context(|| format!("unable to write to file {:?}", destination))
+}
+
+// Write the contents to a temporary file in the same directory and rename it
+// over the destination, so that the destination is never half-written, e.g.
+// when replacing a running executable. The temporary file is synced and gets
+// its owner and mode before the rename
+pub fn write_to_file_atomic(
+    destination: &Utf8Path,
+    contents: &[u8],
+    mode: u32,
+    owner: Option<&str>,
+) -> Result<()> {
+    let temp_file = destination.with_file_name(format!(
+        ".{}.rinstall-{}",
+        destination.file_name().unwrap_or_default(),
+        process::id()
+    ));
+    let result = write_contents(&temp_file, contents)
+        .and_then(|file| {
+            file.sync_all()
+                .with_context(|| format!("unable to sync file {:?}", temp_file))
+        })
+        // Changing the owner clears the setuid and setgid bits, set the mode afterwards
+        .and_then(|()| owner.map_or(Ok(()), |owner| set_owner(&temp_file, owner)))
+        .and_then(|()| set_mode(&temp_file, mode))
+        .and_then(|()| {
+            fs::rename(&temp_file, destination)
+                .with_context(|| format!("unable to rename {:?} to {:?}", temp_file, destination))
+        });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_file);
+    }
+
+    result
+}
+
+// Create a directory and all its missing parents, setting the permissions
+// explicitly so that the umask does not change them. The directories that already
+// exist are never changed, so their permissions are not widened. Return the
+// created directories
+pub fn create_dir_all_with_mode(
+    path: &Utf8Path,
+    mode: u32,
+) -> Result<Vec<Utf8PathBuf>> {
+    // The last ancestor of a relative path is empty
+    let missing: Vec<&Utf8Path> = path
+        .ancestors()
+        .take_while(|dir| !dir.as_str().is_empty() && !dir.exists())
+        .collect();
+    let mut created = Vec::new();
+    for dir in missing.into_iter().rev() {
+        match fs::create_dir(dir) {
+            Ok(()) => {}
+            // Created in the meantime by someone else, leave it as it is
+            Err(err) if err.kind() == ErrorKind::AlreadyExists && dir.is_dir() => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("unable to create directory {:?}", dir))
+            }
+        }
+        set_mode(dir, mode)?;
+        created.push(dir.to_path_buf());
+    }
+    // Deepest first, like the ancestors
+    created.reverse();
+
+    Ok(created)
+}
+
+pub fn set_mode(
+    path: &Utf8Path,
+    mode: u32,
+) -> Result<()> {
+    fs::set_permissions(path, Permissions::from_mode(mode))
+        .with_context(|| format!("unable to set permissions of {:?}", path))
+}
+
+// Change the owner of a file, given as user[:group] with either names or ids
+pub fn set_owner(
+    path: &Utf8Path,
+    owner: &str,
+) -> Result<()> {
+    let (user, group) = owner.split_once(':').unwrap_or((owner, ""));
+    let uid = if user.is_empty() {
+        None
+    } else {
+        Some(user_id(user)?)
+    };
+    let gid = if group.is_empty() {
+        None
+    } else {
+        Some(group_id(group)?)
+    };
+    chown(path, uid, gid).with_context(|| format!("unable to change the owner of {:?}", path))
+}
+
+fn user_id(user: &str) -> Result<u32> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    let name = CString::new(user).with_context(|| format!("invalid user name {:?}", user))?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    ensure!(!passwd.is_null(), "user {:?} does not exist", user);
+    Ok(unsafe { (*passwd).pw_uid })
+}
+
+fn group_id(group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(group).with_context(|| format!("invalid group name {:?}", group))?;
+    let group_entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    ensure!(!group_entry.is_null(), "group {:?} does not exist", group);
+    Ok(unsafe { (*group_entry).gr_gid })
+}
+
+// The umask of the process, which can only be read by changing it
+pub fn current_umask() -> u32 {
+    let umask = unsafe { libc::umask(0o022) };
+    unsafe { libc::umask(umask) };
+    umask as u32
+}
+
+// Label an action that is not performed because it is a dry run
+pub fn would(action: &str) -> String {
+    format!("{} Would {}", "DRY RUN".yellow().bold(), action)
+}
+
+// Warn that the user mode has been chosen implicitly
+pub fn warn_implicit_user_mode(
+    system: bool,
+    user: bool,
+) {
+    if !system && !user {
+        warn!(
+            "neither {} nor {} has been given, defaulting to {} (the default might change in the future)",
+            "--system".bright_black().italic(),
+            "--user".bright_black().italic(),
+            "--user".bright_black().italic(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn create_deeply_nested_dirs() {
+        let root = Utf8PathBuf::from_path_buf(env::temp_dir())
+            .unwrap()
+            .join(format!("rinstall-nested-dirs-{}", process::id()));
+        fs::create_dir(&root).unwrap();
+        set_mode(&root, 0o755).unwrap();
+        let destination = root.join("a/b/c/d/e");
+
+        let created = create_dir_all_with_mode(&destination, 0o750).unwrap();
+        assert_eq!(
+            created,
+            ["a/b/c/d/e", "a/b/c/d", "a/b/c", "a/b", "a"].map(|dir| root.join(dir))
+        );
+        for dir in &created {
+            assert_eq!(
+                fs::metadata(dir).unwrap().permissions().mode() & 0o777,
+                0o750
+            );
+        }
+        // The directories that already existed are left as they are
+        assert_eq!(
+            fs::metadata(&root).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        assert!(create_dir_all_with_mode(&destination, 0o700)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            fs::metadata(&destination).unwrap().permissions().mode() & 0o777,
+            0o750
+        );
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn atomic_write_sets_mode_before_rename() {
+        let root = Utf8PathBuf::from_path_buf(env::temp_dir())
+            .unwrap()
+            .join(format!("rinstall-atomic-write-{}", process::id()));
+        fs::create_dir(&root).unwrap();
+        let destination = root.join("foo");
+        fs::write(&destination, "old").unwrap();
+
+        write_to_file_atomic(&destination, b"new", 0o750, None).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), b"new");
+        assert_eq!(
+            fs::metadata(&destination).unwrap().permissions().mode() & 0o777,
+            0o750
+        );
+        // Only the destination is left, the temporary file has been renamed
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 1);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}